@@ -0,0 +1,79 @@
+//! Ed25519-based connection handshake.
+//!
+//! `node_id` is not a bare client-supplied string: it is the hex encoding of an
+//! ed25519 public key, and a client must prove possession of the matching secret
+//! key by signing `ticket || server_nonce` before the server honors its `Register`.
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rand::RngCore;
+
+/// Size of the server-issued authentication nonce, in bytes.
+pub const NONCE_LEN: usize = 32;
+
+/// Generate a fresh random nonce, hex-encoded for transport over the `ServerMessage::Nonce` frame.
+pub fn generate_nonce() -> String {
+    let mut bytes = [0u8; NONCE_LEN];
+    rand::rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Errors produced while verifying a `Register` handshake.
+#[derive(Debug)]
+pub enum AuthError {
+    MalformedPublicKey,
+    MalformedSignature,
+    NodeIdMismatch,
+    SignatureInvalid,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MalformedPublicKey => write!(f, "malformed public key"),
+            AuthError::MalformedSignature => write!(f, "malformed signature"),
+            AuthError::NodeIdMismatch => write!(f, "node_id does not match public key"),
+            AuthError::SignatureInvalid => write!(f, "signature verification failed"),
+        }
+    }
+}
+
+/// Verify a `Register` handshake and return the authenticated `node_id`.
+///
+/// `node_id` must equal the hex encoding of `public_key` (the server derives identity
+/// from the key rather than trusting the field), and `signature` must verify over
+/// `ticket || server_nonce` under that key.
+pub fn verify_registration(
+    node_id: &str,
+    public_key_hex: &str,
+    signature_hex: &str,
+    ticket: &str,
+    server_nonce: &str,
+) -> Result<String, AuthError> {
+    let derived_node_id = public_key_hex.to_lowercase();
+    if node_id.to_lowercase() != derived_node_id {
+        return Err(AuthError::NodeIdMismatch);
+    }
+
+    let key_bytes: [u8; 32] = hex::decode(public_key_hex)
+        .map_err(|_| AuthError::MalformedPublicKey)?
+        .try_into()
+        .map_err(|_| AuthError::MalformedPublicKey)?;
+    let verifying_key =
+        VerifyingKey::from_bytes(&key_bytes).map_err(|_| AuthError::MalformedPublicKey)?;
+
+    let sig_bytes: [u8; 64] = hex::decode(signature_hex)
+        .map_err(|_| AuthError::MalformedSignature)?
+        .try_into()
+        .map_err(|_| AuthError::MalformedSignature)?;
+    let signature = Signature::from_bytes(&sig_bytes);
+
+    let mut message = Vec::with_capacity(ticket.len() + server_nonce.len());
+    message.extend_from_slice(ticket.as_bytes());
+    message.extend_from_slice(server_nonce.as_bytes());
+
+    verifying_key
+        .verify(&message, &signature)
+        .map_err(|_| AuthError::SignatureInvalid)?;
+
+    Ok(derived_node_id)
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use prometheus::{IntGauge, Registry};
 use tokio::sync::Mutex;
 use tracing::warn;
 
@@ -8,11 +12,97 @@ use tracing::warn;
 const MAX_TOTAL_CONNECTIONS: usize = 1000;
 const MAX_PER_IP: usize = 5;
 
+/// A single IP address or an `address/prefix_len` CIDR block, for reserving or
+/// banning a whole range (e.g. a cloud provider's NAT egress block) in one entry
+/// instead of listing every address in it. An address with no `/` is a /32 or /128.
+#[derive(Debug, Clone, Copy)]
+pub struct CidrBlock {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl CidrBlock {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = mask_u32(self.prefix_len, 32);
+                (u32::from(net) & mask) == (u32::from(*addr) & mask)
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = mask_u128(self.prefix_len, 128);
+                (u128::from(net) & mask) == (u128::from(*addr) & mask)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Top `prefix_len` bits set, for a 32-bit address; a shift of exactly `width`
+/// would overflow, so a `/0` block (matching everything) is handled separately.
+fn mask_u32(prefix_len: u8, width: u32) -> u32 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u32::MAX << (width - prefix_len as u32)
+    }
+}
+
+/// Same as [`mask_u32`] for a 128-bit (IPv6) address
+fn mask_u128(prefix_len: u8, width: u32) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u128::MAX << (width - prefix_len as u32)
+    }
+}
+
+impl FromStr for CidrBlock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((addr, len)) => {
+                let network: IpAddr = addr
+                    .parse()
+                    .map_err(|_| format!("invalid IP address: {addr}"))?;
+                let prefix_len: u8 = len
+                    .parse()
+                    .map_err(|_| format!("invalid prefix length: {len}"))?;
+                let max = if network.is_ipv4() { 32 } else { 128 };
+                if prefix_len > max {
+                    return Err(format!(
+                        "prefix length {prefix_len} exceeds {max} for {network}"
+                    ));
+                }
+                Ok(CidrBlock {
+                    network,
+                    prefix_len,
+                })
+            }
+            None => {
+                let network: IpAddr = s
+                    .parse()
+                    .map_err(|_| format!("invalid IP address or CIDR block: {s}"))?;
+                let prefix_len = if network.is_ipv4() { 32 } else { 128 };
+                Ok(CidrBlock {
+                    network,
+                    prefix_len,
+                })
+            }
+        }
+    }
+}
+
+/// GCRA message rate limit: sustained rate and burst tolerance per IP
+const MESSAGE_RATE_PER_SEC: u32 = 20;
+const MESSAGE_BURST: u32 = 10;
+
 /// Error when connection limit is reached
 #[derive(Debug)]
 pub enum LimitError {
     TotalLimitReached,
     IpLimitReached,
+    Banned,
 }
 
 impl std::fmt::Display for LimitError {
@@ -20,41 +110,114 @@ impl std::fmt::Display for LimitError {
         match self {
             LimitError::TotalLimitReached => write!(f, "Maximum total connections reached"),
             LimitError::IpLimitReached => write!(f, "Maximum connections per IP reached"),
+            LimitError::Banned => write!(f, "IP is banned"),
+        }
+    }
+}
+
+impl LimitError {
+    /// Label value for the `reason` dimension of the rejected-connections metric
+    pub fn metric_reason(&self) -> &'static str {
+        match self {
+            LimitError::TotalLimitReached => "total_limit",
+            LimitError::IpLimitReached => "ip_limit",
+            LimitError::Banned => "banned",
         }
     }
 }
 
 /// Connection limiter to prevent DoS attacks
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ConnectionLimiter {
     inner: Arc<Mutex<ConnectionLimiterInner>>,
+    active_connections: IntGauge,
+    per_ip_connections: IntGauge,
 }
 
-#[derive(Debug)]
 struct ConnectionLimiterInner {
     /// Connections per IP address
     connections_per_ip: HashMap<IpAddr, usize>,
     /// Total active connections
     total_connections: usize,
+    /// GCRA theoretical arrival time (TAT) per IP, for message-level rate limiting
+    message_tat: HashMap<IpAddr, Instant>,
+    /// IPs or CIDR blocks exempt from both the total and per-IP caps (trusted
+    /// relays, monitoring hosts); a bare IP is stored as a /32 or /128
+    reserved: Vec<CidrBlock>,
+    /// IPs or CIDR blocks rejected outright, before any limit counting
+    banned: Vec<CidrBlock>,
+}
+
+impl ConnectionLimiterInner {
+    fn is_reserved(&self, ip: &IpAddr) -> bool {
+        self.reserved.iter().any(|c| c.contains(ip))
+    }
+
+    fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.banned.iter().any(|c| c.contains(ip))
+    }
 }
 
 impl ConnectionLimiter {
-    pub fn new() -> Self {
+    /// Registers `nodemaster_active_connections`/`nodemaster_per_ip_connections` on
+    /// `registry`, so the process-wide `/metrics` endpoint reflects this limiter's
+    /// live state without the accept loop having to poll `stats()` and push it through.
+    pub fn new(registry: &Registry) -> Self {
+        let active_connections = IntGauge::new(
+            "nodemaster_active_connections",
+            "Total active connections, excluding reserved IPs",
+        )
+        .unwrap();
+        let per_ip_connections = IntGauge::new(
+            "nodemaster_per_ip_connections",
+            "Distinct IPs currently holding a connection",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(active_connections.clone()))
+            .expect("unique metric names");
+        registry
+            .register(Box::new(per_ip_connections.clone()))
+            .expect("unique metric names");
+
         Self {
             inner: Arc::new(Mutex::new(ConnectionLimiterInner {
                 connections_per_ip: HashMap::new(),
                 total_connections: 0,
+                message_tat: HashMap::new(),
+                reserved: Vec::new(),
+                banned: Vec::new(),
             })),
+            active_connections,
+            per_ip_connections,
         }
     }
 
+    /// Exempt every IP in `cidr` (a bare address is a /32 or /128) from the total
+    /// and per-IP connection caps
+    pub async fn add_reserved(&self, cidr: CidrBlock) {
+        self.inner.lock().await.reserved.push(cidr);
+    }
+
+    /// Reject every future connection from any IP in `cidr`
+    pub async fn ban(&self, cidr: CidrBlock) {
+        self.inner.lock().await.banned.push(cidr);
+    }
+
     /// Try to accept a new connection from the given IP
     /// Returns Ok(()) if allowed, Err(LimitError) if rejected
     pub async fn try_connect(&self, ip: IpAddr) -> Result<(), LimitError> {
         let mut inner = self.inner.lock().await;
 
-        // Check total limit
-        if inner.total_connections >= MAX_TOTAL_CONNECTIONS {
+        if inner.is_banned(&ip) {
+            warn!("[LIMIT] Rejected banned IP {}", ip);
+            return Err(LimitError::Banned);
+        }
+
+        let reserved = inner.is_reserved(&ip);
+
+        // Check total limit; reserved IPs never count against it
+        if !reserved && inner.total_connections >= MAX_TOTAL_CONNECTIONS {
             warn!(
                 "[LIMIT] Total connection limit reached ({})",
                 MAX_TOTAL_CONNECTIONS
@@ -62,17 +225,24 @@ impl ConnectionLimiter {
             return Err(LimitError::TotalLimitReached);
         }
 
-        // Check per-IP limit
+        // Check per-IP limit; reserved IPs bypass this too
         let ip_count = inner.connections_per_ip.get(&ip).copied().unwrap_or(0);
-        if ip_count >= MAX_PER_IP {
+        if !reserved && ip_count >= MAX_PER_IP {
             warn!("[LIMIT] IP {} exceeded limit ({})", ip, MAX_PER_IP);
             return Err(LimitError::IpLimitReached);
         }
 
-        // Accept connection
-        inner.total_connections += 1;
+        // Accept connection; reserved IPs are tracked per-IP but left out of the
+        // total so they can't crowd out normal sessions
+        if !reserved {
+            inner.total_connections += 1;
+        }
         *inner.connections_per_ip.entry(ip).or_insert(0) += 1;
 
+        self.active_connections.set(inner.total_connections as i64);
+        self.per_ip_connections
+            .set(inner.connections_per_ip.len() as i64);
+
         Ok(())
     }
 
@@ -80,7 +250,9 @@ impl ConnectionLimiter {
     pub async fn disconnect(&self, ip: IpAddr) {
         let mut inner = self.inner.lock().await;
 
-        inner.total_connections = inner.total_connections.saturating_sub(1);
+        if !inner.is_reserved(&ip) {
+            inner.total_connections = inner.total_connections.saturating_sub(1);
+        }
 
         if let Some(count) = inner.connections_per_ip.get_mut(&ip) {
             *count = count.saturating_sub(1);
@@ -88,12 +260,35 @@ impl ConnectionLimiter {
                 inner.connections_per_ip.remove(&ip);
             }
         }
+
+        // No more connections from this IP: drop its rate-limit state too
+        if !inner.connections_per_ip.contains_key(&ip) {
+            inner.message_tat.remove(&ip);
+        }
+
+        self.active_connections.set(inner.total_connections as i64);
+        self.per_ip_connections
+            .set(inner.connections_per_ip.len() as i64);
     }
 
-    /// Get current stats
-    #[allow(dead_code)]
-    pub async fn stats(&self) -> (usize, usize) {
-        let inner = self.inner.lock().await;
-        (inner.total_connections, inner.connections_per_ip.len())
+    /// GCRA check: allow a message from `ip` at the current time, or reject if it
+    /// would exceed `MESSAGE_RATE_PER_SEC` with `MESSAGE_BURST` burst tolerance.
+    pub async fn check_message_rate(&self, ip: IpAddr) -> bool {
+        let emission_interval = Duration::from_secs_f64(1.0 / MESSAGE_RATE_PER_SEC as f64);
+        let burst_tolerance = emission_interval * MESSAGE_BURST;
+
+        let mut inner = self.inner.lock().await;
+        let now = Instant::now();
+        let tat = inner.message_tat.get(&ip).copied().unwrap_or(now);
+
+        if tat <= now {
+            inner.message_tat.insert(ip, now + emission_interval);
+            true
+        } else if tat - now <= burst_tolerance {
+            inner.message_tat.insert(ip, tat + emission_interval);
+            true
+        } else {
+            false
+        }
     }
 }
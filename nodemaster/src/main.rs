@@ -1,16 +1,53 @@
+mod auth;
 mod connection_limiter;
+mod metrics;
 mod protocol;
 mod room;
 mod server;
+mod tls;
 
+use std::path::PathBuf;
+
+use clap::Parser;
 use tokio::net::TcpListener;
 use tracing::{info, warn};
 
-use connection_limiter::ConnectionLimiter;
+use connection_limiter::{CidrBlock, ConnectionLimiter};
+use metrics::Metrics;
 
 /// NodeMaster server port
 const PORT: u16 = 31337;
 
+/// Port for the Prometheus `/metrics` and `/healthz` endpoints
+const METRICS_PORT: u16 = 31338;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Serve wss:// instead of ws://, wrapping accepted connections in a TLS handshake
+    #[arg(long)]
+    tls: bool,
+
+    /// PEM certificate chain to use for TLS (requires --key). Without this, a
+    /// self-signed pair is generated at startup for local development.
+    #[arg(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// PEM private key to use for TLS (requires --cert)
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Exempt an IP or CIDR block (e.g. 10.0.0.0/8) from connection limits. May be
+    /// given multiple times.
+    #[arg(long = "reserved")]
+    reserved: Vec<CidrBlock>,
+
+    /// Reject every connection from an IP or CIDR block outright. May be given
+    /// multiple times.
+    #[arg(long = "ban")]
+    banned: Vec<CidrBlock>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize logging
@@ -19,13 +56,46 @@ async fn main() -> anyhow::Result<()> {
         .with_thread_ids(false)
         .init();
 
+    let args = Args::parse();
+
+    let tls_acceptor = if args.tls {
+        Some(tls::build_acceptor(args.cert.as_deref(), args.key.as_deref())?)
+    } else {
+        None
+    };
+
     let addr = format!("0.0.0.0:{}", PORT);
     let listener = TcpListener::bind(&addr).await?;
 
-    info!("NodeMaster server listening on {}", addr);
+    info!(
+        "NodeMaster server listening on {} ({})",
+        addr,
+        if tls_acceptor.is_some() { "wss" } else { "ws" }
+    );
+
+    let metrics = Metrics::new();
+    let rooms = room::RoomManager::new(&metrics.registry());
+    let limiter = ConnectionLimiter::new(&metrics.registry());
+
+    for cidr in &args.reserved {
+        limiter.add_reserved(*cidr).await;
+    }
+    for cidr in &args.banned {
+        limiter.ban(*cidr).await;
+    }
+
+    let metrics_addr = format!("0.0.0.0:{}", METRICS_PORT).parse()?;
+    let metrics_for_http = metrics.clone();
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(metrics_for_http, metrics_addr).await {
+            warn!("Metrics server stopped: {}", e);
+        }
+    });
 
-    let rooms = room::RoomManager::new();
-    let limiter = ConnectionLimiter::new();
+    let rooms_for_maintenance = rooms.clone();
+    tokio::spawn(async move {
+        rooms_for_maintenance.run_maintenance().await;
+    });
 
     loop {
         let (stream, addr) = listener.accept().await?;
@@ -34,15 +104,35 @@ async fn main() -> anyhow::Result<()> {
         let client_ip = addr.ip();
         if let Err(e) = limiter.try_connect(client_ip).await {
             warn!("Connection rejected from {}: {}", addr, e);
+            metrics.inc_rejected_connections(e.metric_reason());
             drop(stream); // Close connection immediately
             continue;
         }
 
         let rooms = rooms.clone();
         let limiter = limiter.clone();
+        let metrics = metrics.clone();
 
-        tokio::spawn(async move {
-            server::handle_connection(stream, addr, rooms, limiter).await;
-        });
+        match tls_acceptor.clone() {
+            Some(acceptor) => {
+                tokio::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            server::handle_connection(tls_stream, addr, rooms, limiter, metrics)
+                                .await;
+                        }
+                        Err(e) => {
+                            warn!("TLS handshake failed for {}: {}", addr, e);
+                            limiter.disconnect(addr.ip()).await;
+                        }
+                    }
+                });
+            }
+            None => {
+                tokio::spawn(async move {
+                    server::handle_connection(stream, addr, rooms, limiter, metrics).await;
+                });
+            }
+        }
     }
 }
@@ -0,0 +1,165 @@
+//! Prometheus metrics and a `/healthz` liveness probe, served on a separate HTTP port.
+//!
+//! `Metrics` owns the shared `Registry` and the event counters server.rs increments
+//! directly (registrations, leaves, handshake failures, ...). Gauges with an obvious
+//! source of truth elsewhere — active rooms/peers, active connections — are instead
+//! registered and kept up to date by `RoomManager`/`ConnectionLimiter` themselves via
+//! the same `Registry`, handed to them at construction through [`Metrics::registry`].
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use prometheus::{Encoder, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+#[derive(Clone)]
+pub struct Metrics(Arc<Inner>);
+
+struct Inner {
+    registry: Registry,
+    pub registrations_total: IntCounter,
+    pub leaves_total: IntCounter,
+    pub handshake_failures_total: IntCounter,
+    /// Rejected connections, split by `reason` (`total_limit` / `ip_limit` / `banned`)
+    pub rejected_connections_total: IntCounterVec,
+    pub rate_limited_total: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let registrations_total = IntCounter::new(
+            "nodemaster_registrations_total",
+            "Total successful room registrations",
+        )
+        .unwrap();
+        let leaves_total =
+            IntCounter::new("nodemaster_leaves_total", "Total room departures").unwrap();
+        let handshake_failures_total = IntCounter::new(
+            "nodemaster_handshake_failures_total",
+            "Total failed authentication handshakes",
+        )
+        .unwrap();
+        let rejected_connections_total = IntCounterVec::new(
+            Opts::new(
+                "nodemaster_rejected_connections_total",
+                "Total connections rejected by the connection limiter, by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let rate_limited_total = IntCounter::new(
+            "nodemaster_rate_limited_total",
+            "Total messages dropped by the per-IP rate limiter",
+        )
+        .unwrap();
+
+        for collector in [
+            Box::new(registrations_total.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(leaves_total.clone()),
+            Box::new(handshake_failures_total.clone()),
+            Box::new(rejected_connections_total.clone()),
+            Box::new(rate_limited_total.clone()),
+        ] {
+            registry.register(collector).expect("unique metric names");
+        }
+
+        Self(Arc::new(Inner {
+            registry,
+            registrations_total,
+            leaves_total,
+            handshake_failures_total,
+            rejected_connections_total,
+            rate_limited_total,
+        }))
+    }
+
+    /// The shared registry backing this `Metrics`, for types such as `RoomManager`
+    /// and `ConnectionLimiter` that register their own gauges directly
+    pub fn registry(&self) -> Registry {
+        self.0.registry.clone()
+    }
+
+    pub fn inc_registrations(&self) {
+        self.0.registrations_total.inc();
+    }
+
+    pub fn inc_leaves(&self) {
+        self.0.leaves_total.inc();
+    }
+
+    pub fn inc_handshake_failures(&self) {
+        self.0.handshake_failures_total.inc();
+    }
+
+    pub fn inc_rejected_connections(&self, reason: &str) {
+        self.0
+            .rejected_connections_total
+            .with_label_values(&[reason])
+            .inc();
+    }
+
+    pub fn inc_rate_limited(&self) {
+        self.0.rate_limited_total.inc();
+    }
+
+    fn gather_text(&self) -> String {
+        let encoder = TextEncoder::new();
+        let metric_families = self.0.registry.gather();
+        let mut buf = Vec::new();
+        encoder.encode(&metric_families, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+/// Serve `/metrics` (Prometheus text format) and `/healthz` on `addr` until the
+/// listener fails to bind. Runs for the lifetime of the process.
+pub async fn serve(metrics: Metrics, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Metrics server listening on {}", addr);
+
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Metrics listener accept failed: {}", e);
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = match stream.read(&mut buf).await {
+                Ok(n) => n,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request
+                .lines()
+                .next()
+                .and_then(|line| line.split_whitespace().nth(1))
+                .unwrap_or("/");
+
+            let (status, content_type, body) = if path == "/healthz" {
+                ("200 OK", "text/plain", "ok".to_string())
+            } else if path == "/metrics" {
+                ("200 OK", "text/plain; version=0.0.4", metrics.gather_text())
+            } else {
+                ("404 Not Found", "text/plain", "not found".to_string())
+            };
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
@@ -4,28 +4,46 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientMessage {
-    /// Register to a ticket room with node_id
-    Register { ticket: String, node_id: String },
-    /// Leave current room
-    Leave,
+    /// Register to a ticket room. `node_id` is the hex encoding of `public_key` and is
+    /// recomputed server-side rather than trusted; `signature` must verify over
+    /// `ticket || server_nonce` under `public_key` or the registration is rejected.
+    Register {
+        ticket: String,
+        node_id: String,
+        public_key: String,
+        signature: String,
+    },
+    /// Leave a single ticket room, keeping membership in any others
+    Leave { ticket: String },
     /// Keep-alive ping
     Ping,
     /// Report a peer has left (Host only)
     ReportPeerLeft { node_id: String },
+    /// Reclaim the membership a prior connection left pending under `token`,
+    /// silently rejoining every ticket room it held without re-broadcasting
+    /// `PeerJoined`/`PeerLeft`
+    Resume { token: String },
 }
 
 /// Server -> Client messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerMessage {
+    /// Fresh random nonce the client must sign to authenticate; sent as the first
+    /// frame after the WebSocket upgrade.
+    Nonce { nonce: String },
     /// Current list of peers in the room
-    Peers { node_ids: Vec<String> },
+    Peers { ticket: String, node_ids: Vec<String> },
     /// A new peer joined
-    PeerJoined { node_id: String },
+    PeerJoined { ticket: String, node_id: String },
     /// A peer left
-    PeerLeft { node_id: String },
+    PeerLeft { ticket: String, node_id: String },
     /// Pong response
     Pong,
+    /// Opaque reconnection token issued on a successful `Register`. Presenting it in a
+    /// `Resume` within `grace_secs` of a disconnect reclaims the same `node_id`'s
+    /// memberships without the rest of the room seeing a `PeerLeft`/`PeerJoined` pair.
+    Session { token: String, grace_secs: u64 },
     /// Error message
     Error { message: String },
 }
@@ -1,16 +1,54 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::time::{Duration, Instant};
+
+use prometheus::{IntGauge, Registry};
+use rand::seq::SliceRandom;
+use tokio::sync::{mpsc, Notify, RwLock};
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
 
 use crate::protocol::ServerMessage;
 
-/// Sender channel for a connected client
-pub type ClientSender = mpsc::UnboundedSender<ServerMessage>;
+/// Sender channel for a connected client. Bounded so a client that stops reading (or
+/// a malicious peer) can't make the server buffer unboundedly many `ServerMessage`s.
+pub type ClientSender = mpsc::Sender<ServerMessage>;
+
+/// Capacity of a client's outbound channel
+pub const CLIENT_CHANNEL_CAPACITY: usize = 200;
+
+/// Shared with a connection's read loop; `notify_one()` tells it to close the socket
+/// even though nothing arrived from the client itself
+pub type CloseSignal = Arc<Notify>;
+
+/// Above this many existing members, a newcomer is handed a random sample of peers
+/// instead of the full list, so the gossip mesh stays sparse rather than O(n^2)
+const MAX_ROOM_SIZE: usize = 64;
+
+/// Size of the random sample handed out once a room exceeds `MAX_ROOM_SIZE`, modeled
+/// on devp2p's ideal-peer-count target
+const IDEAL_PEERS: usize = 32;
+
+/// How often the maintenance sweep runs
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A member is considered dead if we haven't heard from it in this long
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How long a disconnected member's slot is kept pending before its membership is
+/// finalized with the normal `PeerLeft`; must stay well under `LIVENESS_TIMEOUT` so
+/// the two sweeps don't race each other
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(30);
 
 /// Represents a client in a room
 #[derive(Debug, Clone)]
 pub struct RoomMember {
     pub sender: ClientSender,
+    pub close: CloseSignal,
+    pub last_seen: Instant,
+    /// Set while this member's owning connection is disconnected but still inside its
+    /// reconnection grace window; the liveness sweep leaves it alone while set
+    pub pending: bool,
 }
 
 /// A room that holds clients with the same ticket
@@ -27,117 +65,443 @@ impl Room {
         }
     }
 
-    /// Add a member to the room, returns list of existing peer node_ids
-    pub fn add_member(&mut self, node_id: String, sender: ClientSender) -> Vec<String> {
-        // Get existing peers before adding
-        let existing_peers: Vec<String> = self.members.keys().cloned().collect();
+    /// Add a member to the room, returns a bounded list of existing peer node_ids.
+    /// Rooms at or under `MAX_ROOM_SIZE` return every existing peer; larger rooms
+    /// return a random sample of `IDEAL_PEERS` so the gossip mesh stays sparse.
+    ///
+    /// If `node_id` already has a pending (reconnect-grace) slot here — it registered
+    /// fresh instead of resuming while still inside its own grace window — this
+    /// reclaims that slot instead of treating it as a brand-new join: the room never
+    /// saw a `PeerLeft` for it, so it gets no `PeerJoined` either, and it's never
+    /// included in its own `existing_peers`.
+    pub fn add_member(
+        &mut self,
+        ticket: &str,
+        node_id: String,
+        sender: ClientSender,
+        close: CloseSignal,
+    ) -> Vec<String> {
+        let was_pending = self.members.get(&node_id).is_some_and(|m| m.pending);
 
-        // Notify existing members about new peer
-        for member in self.members.values() {
-            let _ = member.sender.send(ServerMessage::PeerJoined {
-                node_id: node_id.clone(),
-            });
+        let mut existing_peers: Vec<String> = self
+            .members
+            .keys()
+            .filter(|id| **id != node_id)
+            .cloned()
+            .collect();
+        if existing_peers.len() > MAX_ROOM_SIZE {
+            existing_peers.shuffle(&mut rand::rng());
+            existing_peers.truncate(IDEAL_PEERS);
+        }
+
+        if !was_pending {
+            self.broadcast(
+                ticket,
+                ServerMessage::PeerJoined {
+                    ticket: ticket.to_string(),
+                    node_id: node_id.clone(),
+                },
+            );
         }
 
         // Add the new member
-        self.members.insert(node_id.clone(), RoomMember { sender });
+        self.members.insert(
+            node_id.clone(),
+            RoomMember {
+                sender,
+                close,
+                last_seen: Instant::now(),
+                pending: false,
+            },
+        );
 
         existing_peers
     }
 
+    /// Every other member's node_id, for handing back to a member reclaiming its own
+    /// slot via `resume_member` (which, unlike `add_member`, never removed it)
+    fn peer_ids_excluding(&self, node_id: &str) -> Vec<String> {
+        self.members
+            .keys()
+            .filter(|id| id.as_str() != node_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Mark `node_id`'s slot pending reconnection: the liveness sweep ignores it and
+    /// no `PeerLeft` goes out unless the grace window expires first
+    fn mark_pending(&mut self, node_id: &str) {
+        if let Some(member) = self.members.get_mut(node_id) {
+            member.pending = true;
+        }
+    }
+
+    /// Reclaim a pending slot for a freshly reconnected client, swapping in its new
+    /// sender/close signal without touching the rest of the room
+    fn resume_member(&mut self, node_id: &str, sender: ClientSender, close: CloseSignal) -> bool {
+        match self.members.get_mut(node_id) {
+            Some(member) if member.pending => {
+                member.sender = sender;
+                member.close = close;
+                member.last_seen = Instant::now();
+                member.pending = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Remove a member from the room
-    pub fn remove_member(&mut self, node_id: &str) {
+    pub fn remove_member(&mut self, ticket: &str, node_id: &str) {
         self.members.remove(node_id);
 
-        // Notify remaining members
-        for member in self.members.values() {
-            let _ = member.sender.send(ServerMessage::PeerLeft {
+        self.broadcast(
+            ticket,
+            ServerMessage::PeerLeft {
+                ticket: ticket.to_string(),
                 node_id: node_id.to_string(),
-            });
+            },
+        );
+    }
+
+    /// Send `msg` to every member, evicting (with the usual `PeerLeft`) any whose
+    /// channel is saturated. A member that far behind a fixed buffer is treated as
+    /// hopelessly stuck rather than given a chance to catch up indefinitely.
+    fn broadcast(&mut self, ticket: &str, msg: ServerMessage) {
+        let mut saturated = Vec::new();
+        for (node_id, member) in &self.members {
+            match member.sender.try_send(msg.clone()) {
+                Ok(()) | Err(mpsc::error::TrySendError::Closed(_)) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => saturated.push(node_id.clone()),
+            }
+        }
+
+        for node_id in saturated {
+            warn!("[ROOM] Evicting slow consumer {} from {}", node_id, ticket);
+            if let Some(member) = self.members.get(&node_id) {
+                member.close.notify_one();
+            }
+            self.remove_member(ticket, &node_id);
         }
     }
 
+    /// Refresh a member's liveness timestamp
+    fn touch(&mut self, node_id: &str) {
+        if let Some(member) = self.members.get_mut(node_id) {
+            member.last_seen = Instant::now();
+        }
+    }
+
+    /// Evict members past the liveness deadline, notifying survivors with
+    /// `PeerLeft` and returning the evicted node_ids. Pending (reconnect-grace)
+    /// members are left alone here; `RoomManager::finalize_expired_reconnects`
+    /// owns their fate instead.
+    fn evict_stale(&mut self, ticket: &str) -> Vec<String> {
+        let deadline = Instant::now() - LIVENESS_TIMEOUT;
+        let stale: Vec<String> = self
+            .members
+            .iter()
+            .filter(|(_, member)| {
+                !member.pending && (member.last_seen < deadline || member.sender.is_closed())
+            })
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+
+        for node_id in &stale {
+            self.remove_member(ticket, node_id);
+        }
+
+        stale
+    }
+
     /// Check if room is empty
     pub fn is_empty(&self) -> bool {
         self.members.is_empty()
     }
 }
 
-/// Manager for all rooms
-#[derive(Debug, Default, Clone)]
+/// A reconnection grace window opened by `RoomManager::begin_reconnect_grace`
+#[derive(Debug)]
+struct PendingReconnect {
+    node_id: String,
+    tickets: HashSet<String>,
+    expires_at: Instant,
+}
+
+/// Manager for all rooms. A single connection may hold membership in several ticket
+/// rooms at once, so membership is indexed by `(node_id, ticket)` rather than a single
+/// current ticket per client.
+#[derive(Clone)]
 pub struct RoomManager {
     /// Map of ticket -> Room
     rooms: Arc<RwLock<HashMap<String, Room>>>,
-    /// Map of node_id -> current ticket (for single-ticket constraint)
-    client_tickets: Arc<RwLock<HashMap<String, String>>>,
+    /// Map of node_id -> set of tickets the client currently holds membership in
+    client_tickets: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+    /// Map of reconnect token -> the grace window it was issued for
+    pending_reconnects: Arc<RwLock<HashMap<String, PendingReconnect>>>,
+    active_rooms: IntGauge,
+    total_peers: IntGauge,
 }
 
 impl RoomManager {
-    pub fn new() -> Self {
+    /// Registers `nodemaster_active_rooms`/`nodemaster_total_peers` on `registry`, so
+    /// the process-wide `/metrics` endpoint reflects this manager's live state
+    /// without server.rs having to compute it and push it through separately.
+    pub fn new(registry: &Registry) -> Self {
+        let active_rooms =
+            IntGauge::new("nodemaster_active_rooms", "Active ticket rooms").unwrap();
+        let total_peers =
+            IntGauge::new("nodemaster_total_peers", "Total registered peers").unwrap();
+        registry
+            .register(Box::new(active_rooms.clone()))
+            .expect("unique metric names");
+        registry
+            .register(Box::new(total_peers.clone()))
+            .expect("unique metric names");
+
         Self {
             rooms: Arc::new(RwLock::new(HashMap::new())),
             client_tickets: Arc::new(RwLock::new(HashMap::new())),
+            pending_reconnects: Arc::new(RwLock::new(HashMap::new())),
+            active_rooms,
+            total_peers,
         }
     }
 
-    /// Register a client to a ticket room
-    /// If client was in another room, they are removed from it first
+    /// Refresh the `active_rooms`/`total_peers` gauges from the current state. Called
+    /// after every mutation so `/metrics` never drifts from the live room/client maps.
+    async fn refresh_gauges(&self) {
+        let rooms = self.rooms.read().await;
+        let clients = self.client_tickets.read().await;
+        self.active_rooms.set(rooms.len() as i64);
+        self.total_peers.set(clients.len() as i64);
+    }
+
+    /// Register a client into a ticket room, in addition to any rooms it's already in
     pub async fn register(
         &self,
         ticket: String,
         node_id: String,
         sender: ClientSender,
+        close: CloseSignal,
     ) -> Vec<String> {
-        // Check if client is already in a room
+        // A client that reconnects via plain Register rather than Resume is still a
+        // legitimate reconnection; drop any grace window left open for it so
+        // `finalize_expired_reconnects` doesn't later purge this brand-new session.
+        self.cancel_pending_reconnect(&node_id).await;
+
         {
-            let client_tickets = self.client_tickets.read().await;
-            if let Some(old_ticket) = client_tickets.get(&node_id) {
-                if old_ticket != &ticket {
-                    // Leave old room first
-                    drop(client_tickets);
-                    self.leave(&node_id).await;
+            let mut client_tickets = self.client_tickets.write().await;
+            client_tickets
+                .entry(node_id.clone())
+                .or_default()
+                .insert(ticket.clone());
+        }
+
+        let peers = {
+            let mut rooms = self.rooms.write().await;
+            let room = rooms.entry(ticket.clone()).or_insert_with(Room::new);
+            room.add_member(&ticket, node_id, sender, close)
+        };
+        self.refresh_gauges().await;
+        peers
+    }
+
+    /// Drop any reconnect grace window open for `node_id`
+    async fn cancel_pending_reconnect(&self, node_id: &str) {
+        let mut pending_reconnects = self.pending_reconnects.write().await;
+        pending_reconnects.retain(|_, pending| pending.node_id != node_id);
+    }
+
+    /// Remove a client from a single ticket room, leaving its other memberships intact
+    pub async fn leave(&self, node_id: &str, ticket: &str) {
+        {
+            let mut client_tickets = self.client_tickets.write().await;
+            if let Some(tickets) = client_tickets.get_mut(node_id) {
+                tickets.remove(ticket);
+                if tickets.is_empty() {
+                    client_tickets.remove(node_id);
                 }
             }
         }
 
-        // Record client's current ticket
         {
-            let mut client_tickets = self.client_tickets.write().await;
-            client_tickets.insert(node_id.clone(), ticket.clone());
+            let mut rooms = self.rooms.write().await;
+            if let Some(room) = rooms.get_mut(ticket) {
+                room.remove_member(ticket, node_id);
+
+                // Clean up empty rooms
+                if room.is_empty() {
+                    rooms.remove(ticket);
+                }
+            }
         }
+        self.refresh_gauges().await;
+    }
+
+    /// Begin a reconnection grace window for `node_id` under `token`: every room it
+    /// currently holds membership in is marked pending instead of torn down, so a
+    /// `resume` within `RECONNECT_GRACE` reclaims it with no `PeerLeft`/`PeerJoined`
+    /// churn. If the window isn't resumed in time, `finalize_expired_reconnects`
+    /// tears it down for real.
+    pub async fn begin_reconnect_grace(&self, token: String, node_id: &str) {
+        let tickets = {
+            let client_tickets = self.client_tickets.read().await;
+            match client_tickets.get(node_id) {
+                Some(tickets) => tickets.clone(),
+                None => return,
+            }
+        };
+
+        {
+            let mut rooms = self.rooms.write().await;
+            for ticket in &tickets {
+                if let Some(room) = rooms.get_mut(ticket) {
+                    room.mark_pending(node_id);
+                }
+            }
+        }
+
+        self.pending_reconnects.write().await.insert(
+            token,
+            PendingReconnect {
+                node_id: node_id.to_string(),
+                tickets,
+                expires_at: Instant::now() + RECONNECT_GRACE,
+            },
+        );
+    }
+
+    /// Reclaim the membership `begin_reconnect_grace` left pending under `token`,
+    /// swapping in the new connection's sender/close signal. Returns the reclaimed
+    /// node_id and, for every ticket it's still a member of, the other peers
+    /// already in that room. `None` if `token` is unknown or already expired.
+    pub async fn resume(
+        &self,
+        token: &str,
+        sender: ClientSender,
+        close: CloseSignal,
+    ) -> Option<(String, Vec<(String, Vec<String>)>)> {
+        let pending = {
+            let mut pending_reconnects = self.pending_reconnects.write().await;
+            let pending = pending_reconnects.get(token)?;
+            if Instant::now() >= pending.expires_at {
+                return None;
+            }
+            pending_reconnects.remove(token).unwrap()
+        };
 
-        // Add to room
         let mut rooms = self.rooms.write().await;
-        let room = rooms.entry(ticket).or_insert_with(Room::new);
-        room.add_member(node_id, sender)
+        let mut resumed = Vec::with_capacity(pending.tickets.len());
+        for ticket in &pending.tickets {
+            if let Some(room) = rooms.get_mut(ticket) {
+                if room.resume_member(&pending.node_id, sender.clone(), close.clone()) {
+                    resumed.push((ticket.clone(), room.peer_ids_excluding(&pending.node_id)));
+                }
+            }
+        }
+
+        Some((pending.node_id, resumed))
+    }
+
+    /// Tear down, with the normal `PeerLeft`, every reconnection grace window that
+    /// expired without being resumed
+    async fn finalize_expired_reconnects(&self) {
+        let now = Instant::now();
+        let expired: Vec<PendingReconnect> = {
+            let mut pending_reconnects = self.pending_reconnects.write().await;
+            let expired_tokens: Vec<String> = pending_reconnects
+                .iter()
+                .filter(|(_, p)| p.expires_at <= now)
+                .map(|(token, _)| token.clone())
+                .collect();
+            expired_tokens
+                .into_iter()
+                .filter_map(|token| pending_reconnects.remove(&token))
+                .collect()
+        };
+
+        for pending in expired {
+            self.leave_all(&pending.node_id).await;
+        }
     }
 
-    /// Remove a client from their current room
-    pub async fn leave(&self, node_id: &str) {
-        // Get and remove the client's ticket
-        let ticket = {
+    /// Remove a client from every ticket room it currently holds membership in
+    pub async fn leave_all(&self, node_id: &str) {
+        let tickets = {
             let mut client_tickets = self.client_tickets.write().await;
-            client_tickets.remove(node_id)
+            client_tickets.remove(node_id).unwrap_or_default()
         };
 
-        if let Some(ticket) = ticket {
+        {
             let mut rooms = self.rooms.write().await;
+            for ticket in tickets {
+                if let Some(room) = rooms.get_mut(&ticket) {
+                    room.remove_member(&ticket, node_id);
+                    if room.is_empty() {
+                        rooms.remove(&ticket);
+                    }
+                }
+            }
+        }
+        self.refresh_gauges().await;
+    }
+
+    /// Refresh liveness for every room a client currently holds membership in;
+    /// called whenever a frame arrives on its connection
+    pub async fn touch(&self, node_id: &str) {
+        let tickets = {
+            let client_tickets = self.client_tickets.read().await;
+            match client_tickets.get(node_id) {
+                Some(tickets) => tickets.clone(),
+                None => return,
+            }
+        };
+
+        let mut rooms = self.rooms.write().await;
+        for ticket in tickets {
             if let Some(room) = rooms.get_mut(&ticket) {
-                room.remove_member(node_id);
+                room.touch(node_id);
+            }
+        }
+    }
 
-                // Clean up empty rooms
+    /// Periodically sweep every room for members past `LIVENESS_TIMEOUT` or whose
+    /// sender channel has already closed, evicting them, and finalize any expired
+    /// reconnection grace windows. Runs until the process exits.
+    pub async fn run_maintenance(&self) {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let mut rooms = self.rooms.write().await;
+            let mut emptied = Vec::new();
+            for (ticket, room) in rooms.iter_mut() {
+                let evicted = room.evict_stale(ticket);
                 if room.is_empty() {
-                    rooms.remove(&ticket);
+                    emptied.push(ticket.clone());
+                }
+                if !evicted.is_empty() {
+                    let mut client_tickets = self.client_tickets.write().await;
+                    for node_id in evicted {
+                        if let Some(tickets) = client_tickets.get_mut(&node_id) {
+                            tickets.remove(ticket);
+                            if tickets.is_empty() {
+                                client_tickets.remove(&node_id);
+                            }
+                        }
+                    }
                 }
             }
-        }
-    }
+            for ticket in emptied {
+                rooms.remove(&ticket);
+            }
+            drop(rooms);
 
-    /// Get stats for logging
-    #[allow(dead_code)]
-    pub async fn stats(&self) -> (usize, usize) {
-        let rooms = self.rooms.read().await;
-        let clients = self.client_tickets.read().await;
-        (rooms.len(), clients.len())
+            self.finalize_expired_reconnects().await;
+            self.refresh_gauges().await;
+        }
     }
 }
@@ -1,22 +1,39 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use futures::{SinkExt, StreamExt};
 use std::time::Duration;
-use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, Notify};
 use tokio_tungstenite::tungstenite::Message;
 use tracing::{error, warn};
 
+use crate::auth;
 use crate::connection_limiter::ConnectionLimiter;
+use crate::metrics::Metrics;
 use crate::protocol::{ClientMessage, ServerMessage};
-use crate::room::RoomManager;
+use crate::room::{RoomManager, CLIENT_CHANNEL_CAPACITY, RECONNECT_GRACE};
 
-/// Handle a single WebSocket connection
-pub async fn handle_connection(
-    stream: TcpStream,
+/// How often the server sends an unsolicited `Pong` to the client. These do not move
+/// the read deadline below, which only a frame arriving from the client pushes
+/// forward: a client that sends nothing for `READ_TIMEOUT` is disconnected even while
+/// these go out
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+
+/// How long a connection may go without a real incoming message before it's
+/// considered dead. Tracked as its own deadline rather than a `timeout()` around the
+/// read future, since `HEARTBEAT_INTERVAL` is shorter than this and would otherwise
+/// restart the read-future-wrapping timeout every tick before it could ever elapse
+const READ_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Handle a single WebSocket connection. Generic over the underlying stream so the
+/// same handshake/message loop serves both plaintext and TLS-wrapped connections.
+pub async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S,
     addr: SocketAddr,
     rooms: RoomManager,
     limiter: ConnectionLimiter,
+    metrics: Metrics,
 ) {
     let ws_stream = match tokio_tungstenite::accept_async(stream).await {
         Ok(ws) => ws,
@@ -30,11 +47,27 @@ pub async fn handle_connection(
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     // Channel for sending messages to this client
-    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
+    let (tx, mut rx) = mpsc::channel::<ServerMessage>(CLIENT_CHANNEL_CAPACITY);
 
-    // Current node_id for this connection
+    // Told by `RoomManager`/`Room` to close even though nothing arrived from the
+    // client itself, e.g. this connection was evicted as a slow consumer
+    let close_signal: Arc<Notify> = Arc::new(Notify::new());
+
+    // Current node_id for this connection, set only once the Register handshake's
+    // signature has been verified
     let mut current_node_id: Option<String> = None;
 
+    // Reconnection token for this session, minted on the first successful Register
+    // and handed back to the client so a later `Resume` can reclaim this node_id's
+    // room memberships across a dropped connection
+    let mut session_token: Option<String> = None;
+
+    // Fresh nonce this connection must sign to authenticate; sent as the first frame
+    let server_nonce = auth::generate_nonce();
+    let _ = tx.try_send(ServerMessage::Nonce {
+        nonce: server_nonce.clone(),
+    });
+
     // Task to forward messages from channel to WebSocket
     let send_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
@@ -51,20 +84,34 @@ pub async fn handle_connection(
         }
     });
 
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    // Only pushed forward on an actual incoming message, so a Pong sent on the
+    // heartbeat tick below doesn't count as "heard from the client"
+    let mut read_deadline = tokio::time::Instant::now() + READ_TIMEOUT;
+
     // Process incoming messages with timeout
     loop {
-        // 45 seconds timeout for heartbeat
         let msg_future = ws_receiver.next();
-        let result = match tokio::time::timeout(Duration::from_secs(45), msg_future).await {
-            Ok(res) => res,
-            Err(_) => {
-                // Timeout exceeded
+        let result = tokio::select! {
+            res = msg_future => res,
+            _ = tokio::time::sleep_until(read_deadline) => {
                 // warn!("Connection timed out for {}", addr); // Optional logging
                 break;
             }
+            _ = close_signal.notified() => {
+                warn!("Closing connection for {} (evicted)", addr);
+                break;
+            }
+            _ = heartbeat.tick() => {
+                let _ = tx.try_send(ServerMessage::Pong);
+                continue;
+            }
         };
 
-        // Handle the message result (same as before, just unwrapped from timeout)
+        read_deadline = tokio::time::Instant::now() + READ_TIMEOUT;
+
+        // Handle the message result
         let result = match result {
             Some(r) => r,
             None => break, // Stream closed
@@ -85,11 +132,26 @@ pub async fn handle_connection(
             }
         };
 
+        // Enforce per-IP message rate limit before doing any further work
+        if !limiter.check_message_rate(addr.ip()).await {
+            warn!("Rate limit exceeded for {}", addr);
+            metrics.inc_rate_limited();
+            let _ = tx.try_send(ServerMessage::Error {
+                message: "Rate limit exceeded".to_string(),
+            });
+            continue;
+        }
+
+        // Refresh liveness so the maintenance sweep doesn't evict an active client
+        if let Some(ref node_id) = current_node_id {
+            rooms.touch(node_id).await;
+        }
+
         // Parse client message
         let client_msg: ClientMessage = match serde_json::from_str(&msg) {
             Ok(m) => m,
             Err(e) => {
-                let _ = tx.send(ServerMessage::Error {
+                let _ = tx.try_send(ServerMessage::Error {
                     message: format!("Invalid message format: {}", e),
                 });
                 continue;
@@ -98,46 +160,132 @@ pub async fn handle_connection(
 
         match client_msg {
             ClientMessage::ReportPeerLeft { node_id } => {
-                // Host reports a peer left - remove them from the room
-                if current_node_id.is_some() {
-                    rooms.leave(&node_id).await;
-                } else {
-                    warn!("Received ReportPeerLeft from unregistered client {}", addr);
+                // Only the authenticated id bound to this connection may be evicted;
+                // the client-supplied node_id is never trusted, same as `Leave`
+                match current_node_id {
+                    Some(ref authenticated_id) => {
+                        if node_id != *authenticated_id {
+                            warn!(
+                                "Rejected ReportPeerLeft for {} from connection authenticated as {}",
+                                node_id, authenticated_id
+                            );
+                        } else {
+                            rooms.leave_all(authenticated_id).await;
+                        }
+                    }
+                    None => {
+                        warn!("Received ReportPeerLeft from unauthenticated client {}", addr);
+                    }
                 }
             }
-            ClientMessage::Register { ticket, node_id } => {
-                // If already registered with different node_id, leave first
+            ClientMessage::Register {
+                ticket,
+                node_id,
+                public_key,
+                signature,
+            } => {
+                let node_id = match auth::verify_registration(
+                    &node_id,
+                    &public_key,
+                    &signature,
+                    &ticket,
+                    &server_nonce,
+                ) {
+                    Ok(verified_id) => verified_id,
+                    Err(e) => {
+                        warn!("Handshake failed for {}: {}", addr, e);
+                        metrics.inc_handshake_failures();
+                        let _ = tx.try_send(ServerMessage::Error {
+                            message: format!("Authentication failed: {}", e),
+                        });
+                        continue;
+                    }
+                };
+
+                // Switching identity mid-connection drops membership in every room
+                // held under the old id; otherwise this just adds another room
                 if let Some(ref old_id) = current_node_id {
                     if old_id != &node_id {
-                        rooms.leave(old_id).await;
+                        rooms.leave_all(old_id).await;
                     }
                 }
 
                 current_node_id = Some(node_id.clone());
 
-                // Register and get existing peers
-                let peers = rooms.register(ticket, node_id, tx.clone()).await;
+                // Register into this ticket room (in addition to any others already held)
+                let peers = rooms
+                    .register(ticket.clone(), node_id, tx.clone(), close_signal.clone())
+                    .await;
+                metrics.inc_registrations();
 
                 // Send current peers to client
-                let _ = tx.send(ServerMessage::Peers { node_ids: peers });
+                let _ = tx.try_send(ServerMessage::Peers {
+                    ticket,
+                    node_ids: peers,
+                });
+
+                // Mint this connection's reconnection token once, on its first
+                // successful registration
+                if session_token.is_none() {
+                    let token = format!("{:032x}", rand::random::<u128>());
+                    let _ = tx.try_send(ServerMessage::Session {
+                        token: token.clone(),
+                        grace_secs: RECONNECT_GRACE.as_secs(),
+                    });
+                    session_token = Some(token);
+                }
             }
 
-            ClientMessage::Leave => {
+            ClientMessage::Resume { token } => {
+                match rooms.resume(&token, tx.clone(), close_signal.clone()).await {
+                    Some((node_id, rejoined)) => {
+                        current_node_id = Some(node_id);
+                        for (ticket, node_ids) in rejoined {
+                            let _ = tx.try_send(ServerMessage::Peers { ticket, node_ids });
+                        }
+
+                        // Mint a fresh reconnection token for this resumed connection,
+                        // same as a plain Register does, so the grace window survives
+                        // repeated reconnects instead of being a one-shot feature
+                        let new_token = format!("{:032x}", rand::random::<u128>());
+                        let _ = tx.try_send(ServerMessage::Session {
+                            token: new_token.clone(),
+                            grace_secs: RECONNECT_GRACE.as_secs(),
+                        });
+                        session_token = Some(new_token);
+                    }
+                    None => {
+                        let _ = tx.try_send(ServerMessage::Error {
+                            message: "Resume token invalid or expired".to_string(),
+                        });
+                    }
+                }
+            }
+
+            ClientMessage::Leave { ticket } => {
                 if let Some(ref node_id) = current_node_id {
-                    rooms.leave(node_id).await;
-                    current_node_id = None;
+                    rooms.leave(node_id, &ticket).await;
+                    metrics.inc_leaves();
                 }
             }
 
             ClientMessage::Ping => {
-                let _ = tx.send(ServerMessage::Pong);
+                let _ = tx.try_send(ServerMessage::Pong);
             }
         }
     }
 
-    // Cleanup on disconnect
+    // Cleanup on disconnect. A connection with a session token gets a reconnection
+    // grace window instead of an immediate leave, so a client that reconnects
+    // quickly doesn't cause PeerLeft/PeerJoined churn for the rest of its rooms.
     if let Some(ref node_id) = current_node_id {
-        rooms.leave(node_id).await;
+        match session_token {
+            Some(token) => rooms.begin_reconnect_grace(token, node_id).await,
+            None => {
+                rooms.leave_all(node_id).await;
+                metrics.inc_leaves();
+            }
+        }
     }
 
     // Release connection slot
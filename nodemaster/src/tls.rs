@@ -0,0 +1,51 @@
+//! Optional TLS for the NodeMaster WebSocket listener.
+//!
+//! When `--tls` is passed, accepted `TcpStream`s are wrapped in a `rustls` server
+//! handshake before the WebSocket upgrade. A cert/key pair can be supplied via
+//! `--cert`/`--key`; without them, a self-signed pair is generated at startup for
+//! local development. Plaintext `ws://` remains the default either way.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
+
+/// Build a `TlsAcceptor` from a cert/key pair on disk, or a freshly generated
+/// self-signed pair if neither path is given.
+pub fn build_acceptor(cert_path: Option<&Path>, key_path: Option<&Path>) -> anyhow::Result<TlsAcceptor> {
+    let (certs, key) = match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => load_from_disk(cert_path, key_path)?,
+        _ => generate_self_signed()?,
+    };
+
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_from_disk(
+    cert_path: &Path,
+    key_path: &Path,
+) -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let cert_pem = fs::read(cert_path)?;
+    let key_pem = fs::read(key_path)?;
+
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice()).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())?
+        .ok_or_else(|| anyhow::anyhow!("no private key found in {}", key_path.display()))?;
+
+    Ok((certs, key))
+}
+
+/// Generate an ephemeral self-signed cert/key pair for `localhost`, for dev use
+/// when `--tls` is passed without `--cert`/`--key`.
+fn generate_self_signed() -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certified_key = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let cert_der = certified_key.cert.der().clone();
+    let key_der = PrivateKeyDer::Pkcs8(certified_key.signing_key.serialize_der().into());
+    Ok((vec![cert_der], key_der))
+}
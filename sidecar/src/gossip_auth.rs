@@ -0,0 +1,109 @@
+//! Signs and verifies gossip payloads with the endpoint's iroh identity key, so a
+//! peer can't forge a [`GossipMessage`] under another peer's `peer_id`. Every
+//! broadcast payload is wrapped in a [`SignedGossipMessage`] envelope signed with the
+//! sender's iroh secret key; on receipt the signature is verified against the
+//! envelope's own `signer_public_key`, not the gossip layer's `delivered_from` — this
+//! is a sparse HyParView/Plumtree mesh, so a message is routinely forwarded by peers
+//! other than its original signer, and `delivered_from` is only the immediate relay.
+//! Each envelope also carries the sender's monotonic `seq`, which [`message_id`]
+//! combines with the signer to give every broadcast a stable identity for dedup
+//! regardless of which relay it arrived through.
+
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use iroh::{EndpointId, SecretKey, Signature};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::GossipMessage;
+
+/// A `GossipMessage` wrapped with the sender's signature over its serialized bytes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SignedGossipMessage {
+    payload_bytes: Vec<u8>,
+    signature: String,
+    signer_public_key: String,
+    seq: u64,
+}
+
+/// Why a received gossip payload was rejected
+#[derive(Debug)]
+pub enum VerifyError {
+    Malformed(String),
+    InvalidSignature,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Malformed(e) => write!(f, "malformed signed message: {}", e),
+            VerifyError::InvalidSignature => write!(f, "signature verification failed"),
+        }
+    }
+}
+
+/// A `GossipMessage` that passed signature verification, along with the original
+/// signer and its sequence number for this broadcast. `signer` is the message's
+/// author, which may differ from the peer it was just relayed by
+pub struct VerifiedMessage {
+    pub msg: GossipMessage,
+    pub signer: String,
+    pub seq: u64,
+}
+
+/// Sign `msg` with `secret_key` under sequence number `seq`, producing the bytes to
+/// hand to `GossipSender::broadcast`
+pub fn sign(secret_key: &SecretKey, msg: &GossipMessage, seq: u64) -> serde_json::Result<Vec<u8>> {
+    let payload_bytes = serde_json::to_vec(msg)?;
+    let signature = secret_key.sign(&payload_bytes);
+    let envelope = SignedGossipMessage {
+        payload_bytes,
+        signature: hex::encode(signature.to_bytes()),
+        signer_public_key: secret_key.public().to_string(),
+        seq,
+    };
+    serde_json::to_vec(&envelope)
+}
+
+/// Verify a received envelope's signature against its own claimed signer, returning
+/// the inner message, signer, and sequence number on success. Deliberately does not
+/// check the claimed signer against the gossip layer's `delivered_from` — in this
+/// sparse mesh a validly-signed message is routinely forwarded by peers other than
+/// its author, and rejecting those would break propagation for everyone not directly
+/// connected to the original sender. `delivered_from` is for the caller to use when
+/// rate-limiting or scoring the immediate relay, not for identity binding.
+pub fn verify(bytes: &[u8]) -> Result<VerifiedMessage, VerifyError> {
+    let envelope: SignedGossipMessage =
+        serde_json::from_slice(bytes).map_err(|e| VerifyError::Malformed(e.to_string()))?;
+
+    let signer = EndpointId::from_str(&envelope.signer_public_key)
+        .map_err(|e| VerifyError::Malformed(e.to_string()))?;
+
+    let sig_bytes = hex::decode(&envelope.signature)
+        .map_err(|e| VerifyError::Malformed(e.to_string()))?;
+    let sig_bytes: [u8; 64] = sig_bytes
+        .try_into()
+        .map_err(|_| VerifyError::Malformed("signature must be 64 bytes".to_string()))?;
+    let signature = Signature::from_bytes(&sig_bytes);
+
+    signer
+        .verify(&envelope.payload_bytes, &signature)
+        .map_err(|_| VerifyError::InvalidSignature)?;
+
+    let msg = serde_json::from_slice(&envelope.payload_bytes)
+        .map_err(|e| VerifyError::Malformed(e.to_string()))?;
+    Ok(VerifiedMessage {
+        msg,
+        signer: envelope.signer_public_key,
+        seq: envelope.seq,
+    })
+}
+
+/// Derive a stable id for a broadcast from its signer and sequence number, used to
+/// dedup the same message arriving via multiple gossip paths
+pub fn message_id(signer_public_key: &str, seq: u64) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    signer_public_key.hash(&mut hasher);
+    seq.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
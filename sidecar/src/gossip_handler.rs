@@ -0,0 +1,520 @@
+//! Strategy-based dispatch for incoming gossip events. [`spawn_gossip_receiver`] (in
+//! `server.rs`) only knows how to drain the [`iroh_gossip::api::GossipReceiver`] stream,
+//! verify signatures, dedup, and rate-limit; what happens with a given [`GossipMessage`]
+//! is entirely up to whichever [`GossipHandler`] the room was built with. Adding a new
+//! behavior (chat, presence, ...) means writing a new handler, not editing the receive
+//! loop.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use iroh::{EndpointId, SecretKey};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+use crate::gossip_auth;
+use crate::protocol::{GossipMessage, ServerMessage, SkinEntry};
+
+/// State request replies are delayed by a random jitter in this range so that not
+/// every peer on the topic answers a `StateRequest` at once
+const STATE_REPLY_JITTER: std::ops::Range<u64> = 50..300;
+
+/// A peer broadcasting more than this many messages within [`RATE_WINDOW`] gets its
+/// messages dropped and a `PeerThrottled` event emitted
+const MAX_MESSAGES_PER_WINDOW: u32 = 20;
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// A peer that has sent more than this many malformed/nonsensical frames gets throttled, same
+/// as a peer exceeding the message rate
+const MAX_INVALID_FRAMES: u32 = 5;
+
+/// A `SkinUpdate` still missing acks from known peers is resent at this interval,
+/// up to [`MAX_SKIN_UPDATE_RETRIES`] times, before it's given up on
+const SKIN_UPDATE_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_SKIN_UPDATE_RETRIES: u32 = 5;
+
+/// How many message_ids the dedup cache remembers before evicting the oldest. Mesh
+/// redundancy means a handful of peers can redeliver the same broadcast; this just
+/// needs to outlast that window without growing without bound on a long-lived room.
+const SEEN_CACHE_CAPACITY: usize = 4096;
+
+/// Everything a [`GossipHandler`] needs to react to an event: where to send it, who we
+/// are, and the shared per-room state used to sign, dedup, and score messages
+pub struct HandlerCtx {
+    pub room_id: String,
+    pub my_node_id: String,
+    pub secret_key: SecretKey,
+    pub sender: iroh_gossip::api::GossipSender,
+    pub to_client_tx: mpsc::Sender<ServerMessage>,
+    pub state: Arc<RoomState>,
+}
+
+impl HandlerCtx {
+    /// Sign `msg` under the next sequence number and broadcast it on this room's topic
+    pub async fn broadcast(&self, msg: &GossipMessage) {
+        broadcast_signed(&self.secret_key, &self.sender, &self.state, msg).await;
+    }
+}
+
+/// Sign `msg` under `state`'s next sequence number and broadcast it, for callers (the
+/// `UpdateSkin` handler, the deferred `StateSnapshot` reply task) that don't have a
+/// full [`HandlerCtx`] on hand
+pub async fn broadcast_signed(
+    secret_key: &SecretKey,
+    sender: &iroh_gossip::api::GossipSender,
+    state: &RoomState,
+    msg: &GossipMessage,
+) {
+    let seq = state.next_seq();
+    if let Ok(signed) = gossip_auth::sign(secret_key, msg, seq) {
+        sender.broadcast(Bytes::from(signed)).await.ok();
+    }
+}
+
+/// Broadcast a `SkinUpdate` under `update_id` and keep resending it every
+/// [`SKIN_UPDATE_RETRY_INTERVAL`] while any tracked peer hasn't acked (every peer known
+/// at send time plus any that come up while it's pending, minus any that go down),
+/// up to [`MAX_SKIN_UPDATE_RETRIES`] attempts, then give up. Used by the `UpdateSkin`
+/// client command, which broadcasts outside of any `on_message` dispatch and so has
+/// no `HandlerCtx` to hand.
+pub async fn broadcast_reliable(
+    secret_key: SecretKey,
+    sender: iroh_gossip::api::GossipSender,
+    state: Arc<RoomState>,
+    update_id: String,
+    msg: GossipMessage,
+) {
+    let unacked = state.begin_pending_update(update_id.clone(), msg.clone()).await;
+    broadcast_signed(&secret_key, &sender, &state, &msg).await;
+    if unacked.is_empty() {
+        // No one else in the room to wait on; nothing to retry.
+        state.forget_pending_update(&update_id).await;
+        return;
+    }
+
+    tokio::spawn(async move {
+        for _ in 0..MAX_SKIN_UPDATE_RETRIES {
+            tokio::time::sleep(SKIN_UPDATE_RETRY_INTERVAL).await;
+            match state.retry_pending_update(&update_id).await {
+                Some(msg) => broadcast_signed(&secret_key, &sender, &state, &msg).await,
+                None => return,
+            }
+        }
+        warn!("[TX] Giving up on acks for SkinUpdate {}", update_id);
+        state.forget_pending_update(&update_id).await;
+    });
+}
+
+/// Per-room reaction to gossip events. One instance backs one subscribed topic; new
+/// behaviors (a chat handler, a presence handler) can be added by implementing this
+/// trait instead of touching `handle_connection` or the receive loop.
+#[async_trait]
+pub trait GossipHandler: Send + Sync {
+    /// A verified, deduplicated message was received on the topic
+    async fn on_message(&self, msg: GossipMessage, delivered_from: EndpointId, ctx: &HandlerCtx);
+
+    /// A peer became reachable on the topic
+    async fn on_neighbor_up(&self, _peer: EndpointId, _ctx: &HandlerCtx) {}
+
+    /// A peer became unreachable on the topic
+    async fn on_neighbor_down(&self, _peer: EndpointId, _ctx: &HandlerCtx) {}
+}
+
+/// How many messages a peer has sent within the current rate window
+struct PeerRate {
+    window_start: Instant,
+    count: u32,
+}
+
+/// How many malformed or nonsensical frames a peer has sent. Unlike [`PeerRate`] this
+/// never resets on its own — a peer sending garbage is worth remembering for the life
+/// of the room, not just for a rolling window.
+#[derive(Default)]
+struct InvalidFrameCount(u32);
+
+/// A `SkinUpdate` broadcast still awaiting acks, kept around so it can be resent to
+/// whoever hasn't acked yet
+struct PendingUpdate {
+    msg: GossipMessage,
+    unacked: HashSet<String>,
+}
+
+/// Bounded dedup cache: remembers the most recently seen message_ids, evicting the
+/// oldest once [`SEEN_CACHE_CAPACITY`] is exceeded, so a long-lived, chatty room can't
+/// grow this without limit
+struct SeenCache {
+    order: VecDeque<String>,
+    members: HashSet<String>,
+}
+
+impl SeenCache {
+    /// Record `id`, returning `true` if it was already present
+    fn insert(&mut self, id: String) -> bool {
+        if !self.members.insert(id.clone()) {
+            return true;
+        }
+        self.order.push_back(id);
+        if self.order.len() > SEEN_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.members.remove(&oldest);
+            }
+        }
+        false
+    }
+}
+
+impl Default for SeenCache {
+    fn default() -> Self {
+        Self {
+            order: VecDeque::with_capacity(SEEN_CACHE_CAPACITY),
+            members: HashSet::with_capacity(SEEN_CACHE_CAPACITY),
+        }
+    }
+}
+
+/// Shared, per-room knowledge: known skin state for `StateRequest` snapshots, the
+/// dedup/rate-limiting bookkeeping used by the receive loop, this connection's own
+/// broadcast sequence counter, and in-flight `SkinUpdate` acks.
+#[derive(Default)]
+pub struct RoomState {
+    /// Latest known skin per peer (including ourselves), used to build snapshots
+    pub skins: Mutex<HashMap<String, SkinEntry>>,
+    /// requester_ids this room has already seen a `StateSnapshot` reply for, so a
+    /// peer whose own jittered reply is still pending can suppress it
+    answered_requests: Mutex<HashSet<String>>,
+    /// Our own next outgoing sequence number, for `message_id` generation
+    next_seq: AtomicU64,
+    /// message_ids already seen on this topic, so a message re-delivered over a
+    /// different gossip path isn't forwarded/acked twice
+    seen_messages: Mutex<SeenCache>,
+    /// Per-sender message rate, for detecting a peer flooding the topic
+    peer_rates: Mutex<HashMap<String, PeerRate>>,
+    /// Per-sender count of malformed/nonsensical frames, for detecting a buggy or
+    /// malicious peer independent of how fast it's sending
+    invalid_frames: Mutex<HashMap<String, InvalidFrameCount>>,
+    /// Peers already reported as throttled, so the client isn't spammed with repeat events
+    throttled_peers: Mutex<HashSet<String>>,
+    /// Peers currently reachable on this topic, tracked via `on_neighbor_up`/`_down`
+    known_peers: Mutex<HashSet<String>>,
+    /// update_id -> peers that still haven't acked it
+    pending_updates: Mutex<HashMap<String, PendingUpdate>>,
+    /// Whether the first `NeighborUp` has already broadcast a `StateRequest`. A
+    /// broadcast right after `subscribe` can race ahead of the mesh actually forming,
+    /// so the first `NeighborUp` sends one as well.
+    requested_initial_state: std::sync::atomic::AtomicBool,
+}
+
+impl RoomState {
+    /// `true` if this message_id has already been seen on this topic
+    pub async fn is_duplicate(&self, message_id: &str) -> bool {
+        self.seen_messages.lock().await.insert(message_id.to_string())
+    }
+
+    /// Record a message from `peer_id` and report whether it just tipped that peer
+    /// over the rate limit (i.e. this is the first message to exceed it)
+    pub async fn record_and_check_throttle(&self, peer_id: &str) -> bool {
+        let now = Instant::now();
+        let mut rates = self.peer_rates.lock().await;
+        let rate = rates.entry(peer_id.to_string()).or_insert(PeerRate {
+            window_start: now,
+            count: 0,
+        });
+        if now.duration_since(rate.window_start) > RATE_WINDOW {
+            rate.window_start = now;
+            rate.count = 0;
+        }
+        rate.count += 1;
+        let over_limit = rate.count > MAX_MESSAGES_PER_WINDOW;
+        drop(rates);
+
+        if !over_limit {
+            return false;
+        }
+        self.throttled_peers
+            .lock()
+            .await
+            .insert(peer_id.to_string())
+    }
+
+    /// `true` if `peer_id` is currently throttled
+    pub async fn is_throttled(&self, peer_id: &str) -> bool {
+        self.throttled_peers.lock().await.contains(peer_id)
+    }
+
+    /// Record a malformed/nonsensical frame from `peer_id` and report whether it just
+    /// tipped that peer over [`MAX_INVALID_FRAMES`] (i.e. this is the frame that
+    /// crossed the threshold)
+    pub async fn record_invalid_and_check_throttle(&self, peer_id: &str) -> bool {
+        let mut counts = self.invalid_frames.lock().await;
+        let count = counts.entry(peer_id.to_string()).or_default();
+        count.0 += 1;
+        let over_limit = count.0 > MAX_INVALID_FRAMES;
+        drop(counts);
+
+        if !over_limit {
+            return false;
+        }
+        self.throttled_peers
+            .lock()
+            .await
+            .insert(peer_id.to_string())
+    }
+
+    /// Claim the next outgoing sequence number for a message this room signs and
+    /// broadcasts itself
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Record `peer_id` as reachable. A peer joining after an update went out hasn't
+    /// seen it either, so it's added to every still-pending update's unacked set.
+    pub async fn note_neighbor_up(&self, peer_id: &str) {
+        self.known_peers.lock().await.insert(peer_id.to_string());
+        for pending in self.pending_updates.lock().await.values_mut() {
+            pending.unacked.insert(peer_id.to_string());
+        }
+    }
+
+    /// Record `peer_id` as unreachable and stop waiting on its acks; an update whose
+    /// only holdouts just left is now considered delivered
+    pub async fn note_neighbor_down(&self, peer_id: &str) {
+        self.known_peers.lock().await.remove(peer_id);
+        self.pending_updates.lock().await.retain(|_, pending| {
+            pending.unacked.remove(peer_id);
+            !pending.unacked.is_empty()
+        });
+    }
+
+    /// Register `msg` as sent under `update_id`, awaiting acks from every peer known
+    /// right now. Returns the resulting unacked set, empty if the room has no other
+    /// members to wait on.
+    async fn begin_pending_update(&self, update_id: String, msg: GossipMessage) -> HashSet<String> {
+        let unacked = self.known_peers.lock().await.clone();
+        self.pending_updates.lock().await.insert(
+            update_id,
+            PendingUpdate {
+                msg,
+                unacked: unacked.clone(),
+            },
+        );
+        unacked
+    }
+
+    /// The message to resend for `update_id`, if it's still tracked and still has
+    /// unacked peers
+    async fn retry_pending_update(&self, update_id: &str) -> Option<GossipMessage> {
+        let pending = self.pending_updates.lock().await;
+        let entry = pending.get(update_id)?;
+        if entry.unacked.is_empty() {
+            None
+        } else {
+            Some(entry.msg.clone())
+        }
+    }
+
+    /// Stop tracking `update_id`, whether because it was fully acked or because the
+    /// sender gave up on retrying it
+    async fn forget_pending_update(&self, update_id: &str) {
+        self.pending_updates.lock().await.remove(update_id);
+    }
+
+    /// `true` the first time this is called for this room, so the caller should go
+    /// ahead and broadcast its join-time `StateRequest`; `false` on every later call
+    pub fn note_initial_state_requested(&self) -> bool {
+        !self
+            .requested_initial_state
+            .swap(true, Ordering::Relaxed)
+    }
+
+    /// Record that `peer_id` acked `update_id`, returning `true` if that was the last
+    /// outstanding ack (every peer still tracked for the update has now replied)
+    pub async fn ack_pending_update(&self, update_id: &str, peer_id: &str) -> bool {
+        let mut pending = self.pending_updates.lock().await;
+        if let Some(entry) = pending.get_mut(update_id) {
+            entry.unacked.remove(peer_id);
+            if entry.unacked.is_empty() {
+                pending.remove(update_id);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Keeps every peer's skin choice in sync: broadcasts/acks `SkinUpdate`s and answers
+/// `StateRequest` snapshot queries from newly joined peers
+pub struct SkinSyncHandler;
+
+#[async_trait]
+impl GossipHandler for SkinSyncHandler {
+    async fn on_message(&self, msg: GossipMessage, delivered_from: EndpointId, ctx: &HandlerCtx) {
+        match msg {
+            GossipMessage::SkinUpdate {
+                peer_id,
+                skin_id,
+                champion_id,
+                skin_name,
+                is_custom,
+                update_id,
+            } => {
+                info!("[RX] SkinUpdate from {}: skin_id={}", peer_id, skin_id);
+                ctx.state.skins.lock().await.insert(
+                    peer_id.clone(),
+                    SkinEntry {
+                        peer_id: peer_id.clone(),
+                        skin_id,
+                        champion_id,
+                        skin_name: skin_name.clone(),
+                        is_custom,
+                    },
+                );
+                let _ = ctx
+                    .to_client_tx
+                    .send(ServerMessage::RemoteSkinUpdate {
+                        room_id: ctx.room_id.clone(),
+                        peer_id: peer_id.clone(),
+                        skin_id,
+                        champion_id,
+                        skin_name,
+                        is_custom,
+                    })
+                    .await;
+                let ack = GossipMessage::SkinAck {
+                    target_peer_id: peer_id,
+                    update_id,
+                };
+                ctx.broadcast(&ack).await;
+            }
+            GossipMessage::SkinAck {
+                target_peer_id,
+                update_id,
+            } => {
+                if target_peer_id == ctx.my_node_id {
+                    let fully_delivered = ctx
+                        .state
+                        .ack_pending_update(&update_id, &delivered_from.to_string())
+                        .await;
+                    let _ = ctx
+                        .to_client_tx
+                        .send(ServerMessage::SyncConfirmed {
+                            room_id: ctx.room_id.clone(),
+                            peer_id: delivered_from.to_string(),
+                        })
+                        .await;
+                    if fully_delivered {
+                        let _ = ctx
+                            .to_client_tx
+                            .send(ServerMessage::SkinUpdateDelivered {
+                                room_id: ctx.room_id.clone(),
+                                update_id,
+                            })
+                            .await;
+                    }
+                }
+            }
+            GossipMessage::StateRequest { requester_id } => {
+                if requester_id == ctx.my_node_id {
+                    return;
+                }
+                let already_answered = ctx
+                    .state
+                    .answered_requests
+                    .lock()
+                    .await
+                    .contains(&requester_id);
+                if already_answered {
+                    return;
+                }
+
+                let state = ctx.state.clone();
+                let sender = ctx.sender.clone();
+                let secret_key = ctx.secret_key.clone();
+                tokio::spawn(async move {
+                    let jitter_ms = rand::random::<u64>()
+                        % (STATE_REPLY_JITTER.end - STATE_REPLY_JITTER.start)
+                        + STATE_REPLY_JITTER.start;
+                    tokio::time::sleep(Duration::from_millis(jitter_ms)).await;
+
+                    let mut answered = state.answered_requests.lock().await;
+                    if answered.contains(&requester_id) {
+                        return;
+                    }
+                    answered.insert(requester_id.clone());
+                    drop(answered);
+
+                    let entries: Vec<SkinEntry> =
+                        state.skins.lock().await.values().cloned().collect();
+                    let snapshot = GossipMessage::StateSnapshot {
+                        target_peer_id: requester_id,
+                        entries,
+                    };
+                    broadcast_signed(&secret_key, &sender, &state, &snapshot).await;
+                });
+            }
+            GossipMessage::StateSnapshot {
+                target_peer_id,
+                entries,
+            } => {
+                // Any reply we observe, even one addressed to another peer, means the
+                // requester has been answered; suppress our own pending reply
+                ctx.state
+                    .answered_requests
+                    .lock()
+                    .await
+                    .insert(target_peer_id.clone());
+
+                if target_peer_id == ctx.my_node_id {
+                    for entry in entries {
+                        let _ = ctx
+                            .to_client_tx
+                            .send(ServerMessage::RemoteSkinUpdate {
+                                room_id: ctx.room_id.clone(),
+                                peer_id: entry.peer_id,
+                                skin_id: entry.skin_id,
+                                champion_id: entry.champion_id,
+                                skin_name: entry.skin_name,
+                                is_custom: entry.is_custom,
+                            })
+                            .await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn on_neighbor_up(&self, peer: EndpointId, ctx: &HandlerCtx) {
+        ctx.state.note_neighbor_up(&peer.to_string()).await;
+
+        // The join-time StateRequest may have gone out before any neighbor was
+        // actually reachable; if so, this is the first real chance to ask.
+        if ctx.state.note_initial_state_requested() {
+            let request = GossipMessage::StateRequest {
+                requester_id: ctx.my_node_id.clone(),
+            };
+            ctx.broadcast(&request).await;
+        }
+
+        let _ = ctx
+            .to_client_tx
+            .send(ServerMessage::PeerJoined {
+                room_id: ctx.room_id.clone(),
+                peer_id: peer.to_string(),
+            })
+            .await;
+    }
+
+    async fn on_neighbor_down(&self, peer: EndpointId, ctx: &HandlerCtx) {
+        ctx.state.note_neighbor_down(&peer.to_string()).await;
+        let _ = ctx
+            .to_client_tx
+            .send(ServerMessage::PeerLeft {
+                room_id: ctx.room_id.clone(),
+                peer_id: peer.to_string(),
+            })
+            .await;
+    }
+}
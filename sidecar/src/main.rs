@@ -5,12 +5,15 @@ use iroh_gossip::ALPN as GOSSIP_ALPN;
 use iroh_gossip::net::Gossip;
 use std::path::PathBuf;
 use tokio::net::TcpListener;
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::prelude::*;
 
+mod gossip_auth;
+mod gossip_handler;
 mod nodemaster_client;
 mod protocol;
 mod server;
+mod upnp;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -18,6 +21,11 @@ struct Args {
     /// Directory to store logs
     #[arg(long)]
     log_dir: Option<PathBuf>,
+
+    /// Attempt UPnP/IGD port mapping so peers can connect directly instead of
+    /// relaying, when behind a home router that supports it
+    #[arg(long)]
+    upnp: bool,
 }
 
 #[tokio::main]
@@ -68,11 +76,12 @@ async fn main() -> Result<()> {
         info!("Sidecar started. Logging to stdout (no --log-dir provided)");
     }
 
-    // Generate secret key (for persistent identity, save this to disk)
+    // Generate secret key (for persistent identity, save this to disk). Kept around
+    // after building the endpoint so gossip payloads can be signed with it.
     let secret_key = SecretKey::generate(&mut rand::rng());
 
     let endpoint = Endpoint::builder()
-        .secret_key(secret_key)
+        .secret_key(secret_key.clone())
         .alpns(vec![GOSSIP_ALPN.to_vec()])
         .bind()
         .await?;
@@ -80,6 +89,34 @@ async fn main() -> Result<()> {
     let endpoint_id = endpoint.id();
     info!("Endpoint ID: {}", endpoint_id);
 
+    if args.upnp {
+        if let Some(local_port) = endpoint
+            .bound_sockets()
+            .into_iter()
+            .find_map(|addr| if addr.is_ipv4() { Some(addr.port()) } else { None })
+        {
+            if let Some(mapping) = upnp::map_port(local_port).await {
+                info!("UPnP: mapped external address {}", mapping.external_addr);
+
+                // NOTE: `Endpoint::add_node_addr` registers a *remote* peer's reachable
+                // addresses so this endpoint knows how to dial them — it has no effect
+                // on what this endpoint advertises about itself, so it isn't used here.
+                // Peers currently learn our reachable addresses via the relay/discovery
+                // path (see `endpoint.addr()` below); actually publishing this UPnP
+                // mapping as a direct-connection candidate needs whatever the vendored
+                // iroh version's real API for that is.
+
+                tokio::spawn(async move {
+                    let _ = tokio::signal::ctrl_c().await;
+                    mapping.unmap().await;
+                    std::process::exit(0);
+                });
+            }
+        } else {
+            warn!("--upnp requested but no bound IPv4 socket found on the endpoint");
+        }
+    }
+
     // Wait for connection to relay network to ensure peer discovery works
     info!("Connecting to relay network...");
     endpoint.online().await;
@@ -108,10 +145,12 @@ async fn main() -> Result<()> {
     while let Ok((stream, _)) = listener.accept().await {
         let gossip_clone = gossip.clone();
         let endpoint_id_str = endpoint_id.to_string();
+        let secret_key_clone = secret_key.clone();
         tokio::spawn(server::handle_connection(
             stream,
             gossip_clone,
             endpoint_id_str,
+            secret_key_clone,
         ));
     }
 
@@ -1,15 +1,17 @@
 //! NodeMaster client for peer discovery
 //!
-//! Connects to the NodeMaster server to register and discover peers
-//! in the same ticket room. Includes auto-reconnect with exponential backoff.
+//! Connects to the NodeMaster server to register and discover peers across one or
+//! more ticket rooms at once. Includes auto-reconnect with exponential backoff.
 
+use ed25519_dalek::{Signer, SigningKey};
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, mpsc};
 use tokio_tungstenite::connect_async;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 /// NodeMaster server address
 const NODEMASTER_URL: &str = "ws://127.0.0.1:31337";
@@ -23,31 +25,51 @@ const MAX_RECONNECT_ATTEMPTS: u32 = 10;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum NMClientMessage {
-    Register { ticket: String, node_id: String },
-    Leave,
+    Register {
+        ticket: String,
+        node_id: String,
+        public_key: String,
+        signature: String,
+    },
+    /// Leave a single ticket room, keeping membership in any others
+    Leave { ticket: String },
+    /// Ask NodeMaster to drop `node_id` from every room. NodeMaster honours this only
+    /// when `node_id` is this connection's own authenticated id and ignores a report
+    /// naming any other peer
+    ReportPeerLeft { node_id: String },
     Ping,
+    /// Reclaim the membership a prior connection left pending under `token`,
+    /// silently rejoining every ticket room it held instead of re-registering
+    /// each one from scratch
+    Resume { token: String },
 }
 
 /// NodeMaster -> Client messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum NMServerMessage {
-    Peers { node_ids: Vec<String> },
-    PeerJoined { node_id: String },
-    PeerLeft { node_id: String },
+    /// Nonce to sign for the authentication handshake
+    Nonce { nonce: String },
+    Peers { ticket: String, node_ids: Vec<String> },
+    PeerJoined { ticket: String, node_id: String },
+    PeerLeft { ticket: String, node_id: String },
     Pong,
+    /// Opaque reconnection token issued after a successful `Register`. Presenting it
+    /// in a `Resume` within `grace_secs` of a disconnect reclaims this client's room
+    /// memberships without the rest of the room seeing a `PeerLeft`/`PeerJoined` pair.
+    Session { token: String, grace_secs: u64 },
     Error { message: String },
 }
 
-/// Events from NodeMaster client
+/// Events from NodeMaster client, tagged with the ticket room they occurred in
 #[derive(Debug, Clone)]
 pub enum NodeMasterEvent {
     /// Initial peer list when joining a room
-    PeerList(Vec<String>),
+    PeerList { ticket: String, node_ids: Vec<String> },
     /// A new peer joined
-    PeerJoined(String),
+    PeerJoined { ticket: String, node_id: String },
     /// A peer left
-    PeerLeft(String),
+    PeerLeft { ticket: String, node_id: String },
     /// Connection error
     Error(String),
     /// Disconnected from server
@@ -58,54 +80,103 @@ pub enum NodeMasterEvent {
     Reconnected,
 }
 
-/// Registration info for auto-reconnect
+/// Registration info for auto-reconnect: every ticket room currently joined, all of
+/// which are re-registered on the same connection after a reconnect that has no
+/// session token to resume
 #[derive(Debug, Clone, Default)]
 struct RegistrationInfo {
-    ticket: Option<String>,
-    node_id: Option<String>,
+    tickets: HashSet<String>,
 }
 
+/// Reconnection token handed back by NodeMaster after a `Register`, reused by the
+/// next reconnect attempt to resume these room memberships instead of re-registering
+/// from scratch. Cleared whenever a `Resume` is rejected (token invalid or expired).
+type SessionToken = Arc<Mutex<Option<String>>>;
+
 pub struct NodeMasterClient {
-    tx: mpsc::UnboundedSender<NMClientMessage>,
+    tx: mpsc::UnboundedSender<Command>,
+    node_id: String,
 }
 
 impl NodeMasterClient {
     /// Connect to NodeMaster with auto-reconnect support
+    ///
+    /// Generates a fresh ed25519 keypair for this client; the connection's `node_id`
+    /// is the hex encoding of its public key, and every `Register` handshake signs the
+    /// server-issued nonce with the matching secret key so the id can't be spoofed.
     pub async fn connect(
         nodemaster_url: Option<&str>,
     ) -> Result<(Self, mpsc::UnboundedReceiver<NodeMasterEvent>), String> {
         let url = nodemaster_url.unwrap_or(NODEMASTER_URL).to_string();
+        if !url.starts_with("ws://") && !url.starts_with("wss://") {
+            return Err(format!(
+                "NodeMaster URL must start with ws:// or wss://, got: {}",
+                url
+            ));
+        }
+
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let node_id = hex::encode(signing_key.verifying_key().to_bytes());
 
         // Channel for events from NodeMaster
         let (event_tx, event_rx) = mpsc::unbounded_channel::<NodeMasterEvent>();
 
         // Channel for sending commands
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel::<NMClientMessage>();
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel::<Command>();
 
         // Shared registration info for auto-reconnect
         let registration = Arc::new(Mutex::new(RegistrationInfo::default()));
         let registration_clone = registration.clone();
 
+        // Shared reconnection token, set once NodeMaster issues one and reused by
+        // the next reconnect attempt
+        let session_token: SessionToken = Arc::new(Mutex::new(None));
+        let session_token_clone = session_token.clone();
+
         // Spawn the connection manager with auto-reconnect
         let event_tx_clone = event_tx.clone();
         tokio::spawn(async move {
-            Self::connection_loop(url, cmd_rx, event_tx_clone, registration_clone).await;
+            Self::connection_loop(
+                url,
+                signing_key,
+                cmd_rx,
+                event_tx_clone,
+                registration_clone,
+                session_token_clone,
+            )
+            .await;
         });
 
-        Ok((Self { tx: cmd_tx }, event_rx))
+        Ok((
+            Self {
+                tx: cmd_tx,
+                node_id,
+            },
+            event_rx,
+        ))
+    }
+
+    /// The hex-encoded ed25519 public key identifying this client to NodeMaster
+    pub fn node_id(&self) -> &str {
+        &self.node_id
     }
 
     /// Main connection loop with auto-reconnect
     async fn connection_loop(
         url: String,
-        mut cmd_rx: mpsc::UnboundedReceiver<NMClientMessage>,
+        signing_key: SigningKey,
+        mut cmd_rx: mpsc::UnboundedReceiver<Command>,
         event_tx: mpsc::UnboundedSender<NodeMasterEvent>,
         registration: Arc<Mutex<RegistrationInfo>>,
+        session_token: SessionToken,
     ) {
         let mut backoff_ms = INITIAL_BACKOFF_MS;
         let mut attempt = 0u32;
+        let node_id = hex::encode(signing_key.verifying_key().to_bytes());
+        let transport = if url.starts_with("wss://") { "wss" } else { "ws" };
 
         loop {
+            info!("[NM] Connecting to {} ({})", url, transport);
             match connect_async(&url).await {
                 Ok((ws_stream, _)) => {
                     backoff_ms = INITIAL_BACKOFF_MS; // Reset backoff on success
@@ -118,14 +189,30 @@ impl NodeMasterClient {
 
                     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
-                    // Auto re-register if we have saved registration info
-                    {
-                        let reg = registration.lock().await;
-                        if let (Some(ticket), Some(node_id)) = (&reg.ticket, &reg.node_id) {
-                            let msg = NMClientMessage::Register {
-                                ticket: ticket.clone(),
-                                node_id: node_id.clone(),
-                            };
+                    // Wait for the server's authentication nonce before doing anything else
+                    let server_nonce = match ws_receiver.next().await {
+                        Some(Ok(tokio_tungstenite::tungstenite::Message::Text(text))) => {
+                            match serde_json::from_str::<NMServerMessage>(&text) {
+                                Ok(NMServerMessage::Nonce { nonce }) => nonce,
+                                _ => {
+                                    warn!("[NM] Expected nonce, got something else");
+                                    continue;
+                                }
+                            }
+                        }
+                        _ => {
+                            warn!("[NM] Connection closed before nonce was received");
+                            continue;
+                        }
+                    };
+
+                    // Reclaim the prior session via its reconnection token if we have
+                    // one; otherwise (first connect, or the token was invalidated)
+                    // re-register into every previously-joined ticket room from scratch
+                    let resume_token = session_token.lock().await.clone();
+                    match resume_token {
+                        Some(token) => {
+                            let msg = NMClientMessage::Resume { token };
                             if let Ok(json) = serde_json::to_string(&msg) {
                                 let _ = ws_sender
                                     .send(tokio_tungstenite::tungstenite::Message::Text(
@@ -134,6 +221,20 @@ impl NodeMasterClient {
                                     .await;
                             }
                         }
+                        None => {
+                            let reg = registration.lock().await;
+                            for ticket in &reg.tickets {
+                                let msg =
+                                    build_register(&signing_key, &node_id, ticket, &server_nonce);
+                                if let Ok(json) = serde_json::to_string(&msg) {
+                                    let _ = ws_sender
+                                        .send(tokio_tungstenite::tungstenite::Message::Text(
+                                            json.into(),
+                                        ))
+                                        .await;
+                                }
+                            }
+                        }
                     }
 
                     // Process messages until disconnect
@@ -143,14 +244,14 @@ impl NodeMasterClient {
                         &mut cmd_rx,
                         &event_tx,
                         &registration,
+                        &session_token,
+                        &signing_key,
+                        &node_id,
+                        &server_nonce,
                     )
                     .await;
 
                     match disconnect_reason {
-                        DisconnectReason::Leave => {
-                            let _ = event_tx.send(NodeMasterEvent::Disconnected);
-                            break; // Exit loop, no reconnect
-                        }
                         DisconnectReason::Error(e) => {
                             warn!("[NM] Connection lost: {}", e);
                             // Fall through to reconnect
@@ -202,30 +303,40 @@ impl NodeMasterClient {
                 tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
             >,
         >,
-        cmd_rx: &mut mpsc::UnboundedReceiver<NMClientMessage>,
+        cmd_rx: &mut mpsc::UnboundedReceiver<Command>,
         event_tx: &mpsc::UnboundedSender<NodeMasterEvent>,
         registration: &Arc<Mutex<RegistrationInfo>>,
+        session_token: &SessionToken,
+        signing_key: &SigningKey,
+        node_id: &str,
+        server_nonce: &str,
     ) -> DisconnectReason {
         loop {
             tokio::select! {
                 // Handle outgoing commands
                 Some(cmd) = cmd_rx.recv() => {
-                    // Save registration info for auto-reconnect
-                    if let NMClientMessage::Register { ref ticket, ref node_id } = cmd {
-                        let mut reg = registration.lock().await;
-                        reg.ticket = Some(ticket.clone());
-                        reg.node_id = Some(node_id.clone());
-                    }
-
-                    // Check for Leave command
-                    if matches!(cmd, NMClientMessage::Leave) {
-                        let _ = serde_json::to_string(&cmd)
-                            .map(|json| ws_sender.send(tokio_tungstenite::tungstenite::Message::Text(json.into())));
-                        return DisconnectReason::Leave;
-                    }
+                    let wire_msg = match cmd {
+                        Command::Register(ticket) => {
+                            {
+                                let mut reg = registration.lock().await;
+                                reg.tickets.insert(ticket.clone());
+                            }
+                            build_register(signing_key, node_id, &ticket, server_nonce)
+                        }
+                        Command::Leave(ticket) => {
+                            {
+                                let mut reg = registration.lock().await;
+                                reg.tickets.remove(&ticket);
+                            }
+                            NMClientMessage::Leave { ticket }
+                        }
+                        Command::ReportPeerLeft(node_id) => {
+                            NMClientMessage::ReportPeerLeft { node_id }
+                        }
+                    };
 
                     // Send command
-                    match serde_json::to_string(&cmd) {
+                    match serde_json::to_string(&wire_msg) {
                         Ok(json) => {
                             if ws_sender
                                 .send(tokio_tungstenite::tungstenite::Message::Text(json.into()))
@@ -247,19 +358,28 @@ impl NodeMasterClient {
                         Some(Ok(tokio_tungstenite::tungstenite::Message::Text(text))) => {
                             if let Ok(msg) = serde_json::from_str::<NMServerMessage>(&text) {
                                 let event = match msg {
-                                    NMServerMessage::Peers { node_ids } => {
-
-                                        NodeMasterEvent::PeerList(node_ids)
+                                    NMServerMessage::Peers { ticket, node_ids } => {
+                                        NodeMasterEvent::PeerList { ticket, node_ids }
                                     }
-                                    NMServerMessage::PeerJoined { node_id } => {
-                                        NodeMasterEvent::PeerJoined(node_id)
+                                    NMServerMessage::PeerJoined { ticket, node_id } => {
+                                        NodeMasterEvent::PeerJoined { ticket, node_id }
                                     }
-                                    NMServerMessage::PeerLeft { node_id } => {
-                                        NodeMasterEvent::PeerLeft(node_id)
+                                    NMServerMessage::PeerLeft { ticket, node_id } => {
+                                        NodeMasterEvent::PeerLeft { ticket, node_id }
                                     }
+                                    NMServerMessage::Nonce { .. } => continue,
                                     NMServerMessage::Pong => continue,
+                                    NMServerMessage::Session { token, .. } => {
+                                        let mut stored = session_token.lock().await;
+                                        *stored = Some(token);
+                                        continue;
+                                    }
                                     NMServerMessage::Error { message } => {
                                         warn!("[NM] Error: {}", message);
+                                        // A rejected Resume means the token is no longer
+                                        // valid; drop it so the next reconnect falls back
+                                        // to registering every ticket from scratch
+                                        session_token.lock().await.take();
                                         NodeMasterEvent::Error(message)
                                     }
                                 };
@@ -284,21 +404,52 @@ impl NodeMasterClient {
         }
     }
 
-    /// Register to a ticket room
-    pub fn register(&self, ticket: String, node_id: String) {
-        let _ = self.tx.send(NMClientMessage::Register { ticket, node_id });
+    /// Register into a ticket room, in addition to any already joined
+    pub fn register(&self, ticket: String) {
+        let _ = self.tx.send(Command::Register(ticket));
+    }
+
+    /// Leave a single ticket room, keeping any others joined on this connection
+    pub fn leave(&self, ticket: String) {
+        let _ = self.tx.send(Command::Leave(ticket));
+    }
+
+    /// Send a `ReportPeerLeft` for `node_id`; NodeMaster acts on it only when
+    /// `node_id` is this client's own node_id
+    pub fn report_peer_left(&self, node_id: String) {
+        let _ = self.tx.send(Command::ReportPeerLeft(node_id));
     }
+}
 
-    /// Leave current room
-    #[allow(dead_code)]
-    pub fn leave(&self) {
-        let _ = self.tx.send(NMClientMessage::Leave);
+/// Build a signed `Register` message over the current server nonce
+fn build_register(
+    signing_key: &SigningKey,
+    node_id: &str,
+    ticket: &str,
+    server_nonce: &str,
+) -> NMClientMessage {
+    let mut message = Vec::with_capacity(ticket.len() + server_nonce.len());
+    message.extend_from_slice(ticket.as_bytes());
+    message.extend_from_slice(server_nonce.as_bytes());
+    let signature = signing_key.sign(&message);
+
+    NMClientMessage::Register {
+        ticket: ticket.to_string(),
+        node_id: node_id.to_string(),
+        public_key: node_id.to_string(),
+        signature: hex::encode(signature.to_bytes()),
     }
 }
 
+/// Commands issued by the owning application, distinct from the signed wire protocol
+enum Command {
+    Register(String),
+    Leave(String),
+    ReportPeerLeft(String),
+}
+
 /// Reason for disconnect
 enum DisconnectReason {
-    Leave,
     Error(String),
     ServerClosed,
 }
@@ -3,43 +3,70 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "action", content = "payload")]
 pub enum ClientMessage {
-    CreateTicket,
+    /// `room_id` is a client-chosen label for this room, echoed back in the `room_id`
+    /// field of `ServerMessage` events so the client can demux which of its joined
+    /// rooms an event belongs to; `UntrustedMessage` and `PeerThrottled` carry no
+    /// `room_id`
+    CreateTicket {
+        room_id: String,
+    },
     GetNodeId,
-    JoinTicket(String),
+    JoinTicket {
+        room_id: String,
+        ticket: String,
+    },
     /// Join via NodeMaster server for peer discovery
-    /// Payload is just the ticket (topic hash), NodeMaster provides peer list
+    /// `ticket` is just the topic hash; NodeMaster provides the peer list
     JoinViaNodeMaster {
+        room_id: String,
         ticket: String,
         nodemaster_url: Option<String>,
     },
     UpdateSkin {
+        room_id: String,
         skin_id: u32,
         champion_id: u32,
         skin_name: String,
         is_custom: bool,
     },
-    LeaveRoom,
+    /// Report that a peer has gone away, forwarded to `room_id`'s NodeMaster
+    /// connection (if it has one)
+    ReportPeerLeft {
+        room_id: String,
+        node_id: String,
+    },
+    LeaveRoom {
+        room_id: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "event", content = "data")]
 pub enum ServerMessage {
-    TicketCreated(String),
+    TicketCreated {
+        room_id: String,
+        ticket: String,
+    },
     NodeId(String),
     JoinedRoom {
+        room_id: String,
         ticket: String,
     },
     InvalidTicket {
+        room_id: String,
         ticket: String,
         reason: String,
     },
     PeerJoined {
+        room_id: String,
         peer_id: String,
     },
     PeerLeft {
+        room_id: String,
         peer_id: String,
     },
     RemoteSkinUpdate {
+        room_id: String,
         peer_id: String,
         skin_id: u32,
         champion_id: u32,
@@ -47,6 +74,25 @@ pub enum ServerMessage {
         is_custom: bool,
     },
     SyncConfirmed {
+        room_id: String,
+        peer_id: String,
+    },
+    /// Every peer the sender was still waiting on for a `SkinUpdate` (reachable when it
+    /// went out or since, and not gone down) has now acked it, so retransmission for it
+    /// has stopped
+    SkinUpdateDelivered {
+        room_id: String,
+        update_id: String,
+    },
+    /// A gossip payload failed signature verification and was dropped instead of
+    /// being surfaced to the rest of the app
+    UntrustedMessage {
+        peer_id: String,
+        reason: String,
+    },
+    /// A peer exceeded the per-room message rate or sent too many malformed frames,
+    /// and its messages are being dropped instead of forwarded
+    PeerThrottled {
         peer_id: String,
     },
     Error {
@@ -56,7 +102,19 @@ pub enum ServerMessage {
         level: String,
         message: String,
     },
-    LeftRoom,
+    LeftRoom {
+        room_id: String,
+    },
+}
+
+/// One peer's last-known skin, as carried in a `StateSnapshot` reply
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkinEntry {
+    pub peer_id: String,
+    pub skin_id: u32,
+    pub champion_id: u32,
+    pub skin_name: String,
+    pub is_custom: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -68,8 +126,51 @@ pub enum GossipMessage {
         champion_id: u32,
         skin_name: String,
         is_custom: bool,
+        /// Identifies this broadcast for ack/retransmission purposes, independent of
+        /// the signing sequence number a retransmit is resent under
+        update_id: String,
     },
+    /// Acknowledges having applied the `SkinUpdate` carrying `update_id`
     SkinAck {
         target_peer_id: String,
+        update_id: String,
+    },
+    /// Broadcast by a peer when it joins a topic (right after subscribing on a
+    /// `JoinTicket`, and again on its first `NeighborUp`), asking existing peers
+    /// for their current skin state
+    StateRequest {
+        requester_id: String,
     },
+    /// A reply to a `StateRequest`, addressed to `target_peer_id`. Every other peer
+    /// on the topic also sees this and uses it to suppress its own pending reply.
+    StateSnapshot {
+        target_peer_id: String,
+        entries: Vec<SkinEntry>,
+    },
+}
+
+/// `skin_name` longer than this is rejected rather than forwarded to every peer's UI
+const MAX_SKIN_NAME_LEN: usize = 128;
+
+impl GossipMessage {
+    /// `false` for a frame that deserialized fine but carries nonsensical field
+    /// values a well-behaved client would never send (empty/oversized name, zeroed
+    /// champion id, ...). Checked in the receive loop before any handler runs, so such
+    /// a frame is neither forwarded to the UI nor acked.
+    pub fn is_well_formed(&self) -> bool {
+        match self {
+            GossipMessage::SkinUpdate {
+                champion_id,
+                skin_name,
+                ..
+            } => {
+                *champion_id != 0
+                    && !skin_name.is_empty()
+                    && skin_name.len() <= MAX_SKIN_NAME_LEN
+            }
+            GossipMessage::SkinAck { .. }
+            | GossipMessage::StateRequest { .. }
+            | GossipMessage::StateSnapshot { .. } => true,
+        }
+    }
 }
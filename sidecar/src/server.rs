@@ -1,17 +1,20 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use bytes::Bytes;
 use futures::{SinkExt, StreamExt};
-use iroh::EndpointId;
+use iroh::{EndpointId, SecretKey};
 use iroh_gossip::net::Gossip;
+use sha2::{Digest, Sha256};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio_tungstenite::accept_async;
-use tracing::{error, info, warn};
+use tracing::{error, warn};
 
+use crate::gossip_auth;
+use crate::gossip_handler::{self, GossipHandler, HandlerCtx, RoomState, SkinSyncHandler};
 use crate::nodemaster_client::{NodeMasterClient, NodeMasterEvent};
-use crate::protocol::{ClientMessage, GossipMessage, ServerMessage};
+use crate::protocol::{ClientMessage, GossipMessage, ServerMessage, SkinEntry};
 
 /// Ticket format: "topic_hex|node_id"
 /// This allows peers to bootstrap by knowing each other's node ID
@@ -36,7 +39,152 @@ fn decode_ticket(ticket: &str) -> Option<([u8; 32], Option<EndpointId>)> {
     }
 }
 
-pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: String) {
+/// Domain separator mixed into the ticket hash so this derivation can never collide
+/// with a topic computed for an unrelated purpose from the same bytes
+const TOPIC_DERIVATION_DOMAIN: &[u8] = b"rose-sidecar:topic-from-ticket:v1";
+
+/// Derive a topic from a NodeMaster ticket: if it's already a 32-byte hex string use
+/// it directly, otherwise hash it into 32 bytes with SHA-256. Every sidecar build
+/// needs to derive the same topic for the same ticket, so the hash has to be a fixed,
+/// portable algorithm rather than `std`'s per-process, per-platform `DefaultHasher`.
+fn derive_topic_bytes(ticket: &str) -> [u8; 32] {
+    match hex::decode(ticket) {
+        Ok(bytes) if bytes.len() == 32 => bytes.try_into().unwrap(),
+        _ => {
+            let mut hasher = Sha256::new();
+            hasher.update(TOPIC_DERIVATION_DOMAIN);
+            hasher.update(ticket.as_bytes());
+            hasher.finalize().into()
+        }
+    }
+}
+
+/// Everything a connection is tracking for one joined room. A connection may hold
+/// several of these at once, keyed by gossip topic, so it can stay subscribed to
+/// several lobbies/parties simultaneously instead of tearing down the previous one
+/// on every `JoinTicket`/`CreateTicket`.
+struct RoomHandle {
+    room_id: String,
+    ticket: String,
+    sender: iroh_gossip::api::GossipSender,
+    state: Arc<RoomState>,
+    receiver_task: tokio::task::JoinHandle<()>,
+    nodemaster_client: Option<NodeMasterClient>,
+    nodemaster_event_task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl RoomHandle {
+    /// Tear down every task and connection backing this room
+    fn abort(self) {
+        self.receiver_task.abort();
+        if let Some(handle) = self.nodemaster_event_task {
+            handle.abort();
+        }
+        if let Some(client) = self.nodemaster_client {
+            client.leave(self.ticket);
+        }
+    }
+}
+
+fn find_topic_for_room(
+    rooms: &HashMap<iroh_gossip::TopicId, RoomHandle>,
+    room_id: &str,
+) -> Option<iroh_gossip::TopicId> {
+    rooms
+        .iter()
+        .find(|(_, handle)| handle.room_id == room_id)
+        .map(|(topic_id, _)| *topic_id)
+}
+
+/// Spawn the task that drains one room's gossip stream: verifying every payload's
+/// signature, dropping messages already seen under another path or from a peer over
+/// its rate limit, and delegating whatever survives to `handler`. What actually
+/// happens for a given event is entirely the handler's business; this loop only knows
+/// how to authenticate, dedup, and dispatch.
+fn spawn_gossip_receiver(
+    handler: Arc<dyn GossipHandler>,
+    ctx: HandlerCtx,
+    mut stream: iroh_gossip::api::GossipReceiver,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event_res) = stream.next().await {
+            match event_res {
+                Ok(iroh_gossip::api::Event::Received(msg)) => {
+                    let verified = match gossip_auth::verify(&msg.content) {
+                        Ok(verified) => verified,
+                        Err(e) => {
+                            warn!("[RX] Rejected message from {}: {}", msg.delivered_from, e);
+                            let _ = ctx
+                                .to_client_tx
+                                .send(ServerMessage::UntrustedMessage {
+                                    peer_id: msg.delivered_from.to_string(),
+                                    reason: e.to_string(),
+                                })
+                                .await;
+                            continue;
+                        }
+                    };
+
+                    // Dedup by the message's original signer, not the relay it arrived
+                    // through, so the same broadcast forwarded via two different
+                    // neighbors still collapses to one delivery
+                    let message_id = gossip_auth::message_id(&verified.signer, verified.seq);
+                    if ctx.state.is_duplicate(&message_id).await {
+                        continue;
+                    }
+
+                    // Rate-limiting/throttling stays keyed by the immediate relay,
+                    // since that's the peer actually spending our bandwidth
+                    let peer_id = msg.delivered_from.to_string();
+
+                    if ctx.state.record_and_check_throttle(&peer_id).await {
+                        warn!("[RX] Peer {} exceeded the gossip rate limit", peer_id);
+                        let _ = ctx
+                            .to_client_tx
+                            .send(ServerMessage::PeerThrottled {
+                                peer_id: peer_id.clone(),
+                            })
+                            .await;
+                    }
+                    if ctx.state.is_throttled(&peer_id).await {
+                        continue;
+                    }
+
+                    if !verified.msg.is_well_formed() {
+                        warn!("[RX] Rejected malformed frame from {}", peer_id);
+                        if ctx.state.record_invalid_and_check_throttle(&peer_id).await {
+                            let _ = ctx
+                                .to_client_tx
+                                .send(ServerMessage::PeerThrottled {
+                                    peer_id: peer_id.clone(),
+                                })
+                                .await;
+                        }
+                        continue;
+                    }
+
+                    handler
+                        .on_message(verified.msg, msg.delivered_from, &ctx)
+                        .await;
+                }
+                Ok(iroh_gossip::api::Event::NeighborUp(peer_id)) => {
+                    handler.on_neighbor_up(peer_id, &ctx).await;
+                }
+                Ok(iroh_gossip::api::Event::NeighborDown(peer_id)) => {
+                    handler.on_neighbor_down(peer_id, &ctx).await;
+                }
+                _ => {}
+            }
+        }
+    })
+}
+
+pub async fn handle_connection(
+    stream: TcpStream,
+    gossip: Gossip,
+    my_node_id: String,
+    secret_key: SecretKey,
+) {
     let ws_stream = match accept_async(stream).await {
         Ok(ws) => ws,
         Err(e) => {
@@ -47,12 +195,8 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
 
     let (ws_sender, mut ws_receiver) = ws_stream.split();
 
-    // State
-    let mut current_topic_sender: Option<iroh_gossip::api::GossipSender> = None;
-    let mut _current_topic: Option<iroh_gossip::TopicId> = None;
-    let mut current_receiver_task: Option<tokio::task::JoinHandle<()>> = None;
-    let mut nodemaster_event_task: Option<tokio::task::JoinHandle<()>> = None;
-    let mut _nodemaster_client: Option<NodeMasterClient> = None;
+    // Rooms this connection is currently subscribed to, keyed by gossip topic
+    let mut rooms: HashMap<iroh_gossip::TopicId, RoomHandle> = HashMap::new();
     let (to_client_tx, mut to_client_rx) = mpsc::channel::<ServerMessage>(100);
 
     // Writer task
@@ -86,10 +230,11 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                     let text = msg.to_string();
                     if let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
                         match client_msg {
-                            ClientMessage::CreateTicket => {
-                                // Abort previous receiver task if exists
-                                if let Some(handle) = current_receiver_task.take() {
-                                    handle.abort();
+                            ClientMessage::CreateTicket { room_id } => {
+                                if let Some(topic_id) = find_topic_for_room(&rooms, &room_id) {
+                                    if let Some(handle) = rooms.remove(&topic_id) {
+                                        handle.abort();
+                                    }
                                 }
 
                                 // Generate topic
@@ -102,97 +247,35 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                 // subscribe (no bootstrap peers when creating - we ARE the first peer)
                                 match gossip.subscribe(topic_id, vec![]).await {
                                     Ok(sub) => {
-                                        _current_topic = Some(topic_id);
-                                        let (sender, mut stream) = sub.split();
-                                        current_topic_sender = Some(sender.clone());
-
-                                        // Handle receiver
-                                        let tx_clone = to_client_tx.clone();
-                                        let my_node_id_clone = my_node_id.clone();
-                                        let sender_clone = sender.clone();
-                                        let handle = tokio::spawn(async move {
-                                            while let Some(event_res) = stream.next().await {
-                                                match event_res {
-                                                    Ok(iroh_gossip::api::Event::Received(msg)) => {
-                                                        if let Ok(gossip_msg) =
-                                                            serde_json::from_slice::<GossipMessage>(
-                                                                &msg.content,
-                                                            )
-                                                        {
-                                                            match gossip_msg {
-                                                                GossipMessage::SkinUpdate {
-                                                                    peer_id,
-                                                                    skin_id,
-                                                                    champion_id,
-                                                                    skin_name,
-                                                                    is_custom,
-                                                                } => {
-                                                                    info!(
-                                                                        "[RX] SkinUpdate from {}: skin_id={}",
-                                                                        peer_id, skin_id
-                                                                    );
-                                                                    let _ = tx_clone.send(ServerMessage::RemoteSkinUpdate {
-                                                                        peer_id: peer_id.clone(),
-                                                                        skin_id,
-                                                                        champion_id,
-                                                                        skin_name,
-                                                                        is_custom
-                                                                    }).await;
-                                                                    let ack =
-                                                                        GossipMessage::SkinAck {
-                                                                            target_peer_id: peer_id,
-                                                                        };
-                                                                    if let Ok(json) =
-                                                                        serde_json::to_vec(&ack)
-                                                                    {
-                                                                        sender_clone
-                                                                            .broadcast(Bytes::from(
-                                                                                json,
-                                                                            ))
-                                                                            .await
-                                                                            .ok();
-                                                                    }
-                                                                }
-                                                                GossipMessage::SkinAck {
-                                                                    target_peer_id,
-                                                                } => {
-                                                                    if target_peer_id
-                                                                        == my_node_id_clone
-                                                                    {
-                                                                        let _ = tx_clone.send(ServerMessage::SyncConfirmed {
-                                                                            peer_id: msg.delivered_from.to_string(),
-                                                                        }).await;
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    Ok(iroh_gossip::api::Event::NeighborUp(
-                                                        peer_id,
-                                                    )) => {
-                                                        let _ = tx_clone
-                                                            .send(ServerMessage::PeerJoined {
-                                                                peer_id: peer_id.to_string(),
-                                                            })
-                                                            .await;
-                                                    }
-                                                    Ok(iroh_gossip::api::Event::NeighborDown(
-                                                        peer_id,
-                                                    )) => {
-                                                        let _ = tx_clone
-                                                            .send(ServerMessage::PeerLeft {
-                                                                peer_id: peer_id.to_string(),
-                                                            })
-                                                            .await;
-                                                    }
-                                                    _ => {}
-                                                }
-                                            }
-                                        });
-                                        current_receiver_task = Some(handle);
+                                        let (sender, stream) = sub.split();
+                                        let room_state = Arc::new(RoomState::default());
+                                        let handler: Arc<dyn GossipHandler> =
+                                            Arc::new(SkinSyncHandler);
+                                        let ctx = HandlerCtx {
+                                            room_id: room_id.clone(),
+                                            my_node_id: my_node_id.clone(),
+                                            secret_key: secret_key.clone(),
+                                            sender: sender.clone(),
+                                            to_client_tx: to_client_tx.clone(),
+                                            state: room_state.clone(),
+                                        };
+                                        let receiver_task = spawn_gossip_receiver(handler, ctx, stream);
+
+                                        rooms.insert(
+                                            topic_id,
+                                            RoomHandle {
+                                                room_id: room_id.clone(),
+                                                ticket: ticket.clone(),
+                                                sender,
+                                                state: room_state,
+                                                receiver_task,
+                                                nodemaster_client: None,
+                                                nodemaster_event_task: None,
+                                            },
+                                        );
 
                                         let _ = to_client_tx
-                                            .send(ServerMessage::TicketCreated(ticket))
+                                            .send(ServerMessage::TicketCreated { room_id, ticket })
                                             .await;
                                     }
                                     Err(e) => {
@@ -209,13 +292,16 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                     .send(ServerMessage::NodeId(my_node_id.clone()))
                                     .await;
                             }
-                            ClientMessage::JoinTicket(ticket) => {
+                            ClientMessage::JoinTicket { room_id, ticket } => {
                                 // Decode ticket to get topic + optional bootstrap peer
                                 match decode_ticket(&ticket) {
                                     Some((topic_bytes, bootstrap_node_id)) => {
-                                        // Abort previous receiver task if exists
-                                        if let Some(handle) = current_receiver_task.take() {
-                                            handle.abort();
+                                        if let Some(topic_id) =
+                                            find_topic_for_room(&rooms, &room_id)
+                                        {
+                                            if let Some(handle) = rooms.remove(&topic_id) {
+                                                handle.abort();
+                                            }
                                         }
 
                                         let topic_id = iroh_gossip::TopicId::from(topic_bytes);
@@ -225,118 +311,59 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                             bootstrap_node_id.into_iter().collect();
 
                                         // Subscribe WITH bootstrap peers
-                                        match gossip
-                                            .subscribe(topic_id, bootstrap_peers.clone())
-                                            .await
-                                        {
+                                        match gossip.subscribe(topic_id, bootstrap_peers).await {
                                             Ok(sub) => {
-                                                _current_topic = Some(topic_id);
-
-                                                let (sender, mut stream) = sub.split();
-                                                current_topic_sender = Some(sender.clone());
+                                                let (sender, stream) = sub.split();
+                                                let room_state = Arc::new(RoomState::default());
+                                                let handler: Arc<dyn GossipHandler> =
+                                                    Arc::new(SkinSyncHandler);
+                                                let ctx = HandlerCtx {
+                                                    room_id: room_id.clone(),
+                                                    my_node_id: my_node_id.clone(),
+                                                    secret_key: secret_key.clone(),
+                                                    sender: sender.clone(),
+                                                    to_client_tx: to_client_tx.clone(),
+                                                    state: room_state.clone(),
+                                                };
+                                                let receiver_task =
+                                                    spawn_gossip_receiver(handler, ctx, stream);
+
+                                                // Ask peers already on the topic for their
+                                                // current state, so we don't show stale/empty
+                                                // skins until the next individual SkinUpdate.
+                                                // If the mesh hasn't actually formed yet this
+                                                // goes nowhere; `on_neighbor_up` retries once a
+                                                // peer is reachable.
+                                                let request = GossipMessage::StateRequest {
+                                                    requester_id: my_node_id.clone(),
+                                                };
+                                                gossip_handler::broadcast_signed(
+                                                    &secret_key,
+                                                    &sender,
+                                                    &room_state,
+                                                    &request,
+                                                )
+                                                .await;
+
+                                                rooms.insert(
+                                                    topic_id,
+                                                    RoomHandle {
+                                                        room_id: room_id.clone(),
+                                                        ticket: ticket.clone(),
+                                                        sender,
+                                                        state: room_state,
+                                                        receiver_task,
+                                                        nodemaster_client: None,
+                                                        nodemaster_event_task: None,
+                                                    },
+                                                );
 
-                                                // Send confirmation
                                                 let _ = to_client_tx
                                                     .send(ServerMessage::JoinedRoom {
-                                                        ticket: ticket.clone(),
+                                                        room_id,
+                                                        ticket,
                                                     })
                                                     .await;
-
-                                                let tx_clone = to_client_tx.clone();
-                                                let my_node_id_clone = my_node_id.clone();
-                                                let sender_clone = sender.clone();
-                                                let handle = tokio::spawn(async move {
-                                                    while let Some(event_res) = stream.next().await
-                                                    {
-                                                        match event_res {
-                                                            Ok(iroh_gossip::api::Event::Received(
-                                                                msg,
-                                                            )) => {
-                                                                if let Ok(gossip_msg) =
-                                                                    serde_json::from_slice::<
-                                                                        GossipMessage,
-                                                                    >(
-                                                                        &msg.content
-                                                                    )
-                                                                {
-                                                                    match gossip_msg {
-                                                                        GossipMessage::SkinUpdate {
-                                                                            peer_id,
-                                                                            skin_id,
-                                                                            champion_id,
-                                                                            skin_name,
-                                                                            is_custom,
-                                                                        } => {
-
-                                                                            let _ = tx_clone.send(ServerMessage::RemoteSkinUpdate {
-                                                                                peer_id: peer_id.clone(),
-                                                                                skin_id,
-                                                                                champion_id,
-                                                                                skin_name,
-                                                                                is_custom
-                                                                            }).await;
-
-                                                                            // AUTO ACK
-                                                                            let ack =
-                                                                                GossipMessage::SkinAck {
-                                                                                    target_peer_id: peer_id,
-                                                                                };
-                                                                            if let Ok(json) =
-                                                                                serde_json::to_vec(&ack)
-                                                                            {
-                                                                                sender_clone
-                                                                                    .broadcast(
-                                                                                        Bytes::from(
-                                                                                            json,
-                                                                                        ),
-                                                                                    )
-                                                                                    .await
-                                                                                    .ok();
-                                                                            }
-                                                                        }
-                                                                        GossipMessage::SkinAck {
-                                                                            target_peer_id,
-                                                                        } => {
-                                                                            if target_peer_id
-                                                                                == my_node_id_clone
-                                                                            {
-                                                                                let _ = tx_clone.send(ServerMessage::SyncConfirmed {
-                                                                                    peer_id: msg.delivered_from.to_string(),
-                                                                                }).await;
-                                                                            }
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                            Ok(
-                                                                iroh_gossip::api::Event::NeighborUp(
-                                                                    peer_id,
-                                                                ),
-                                                            ) => {
-
-                                                                let _ = tx_clone
-                                                                    .send(ServerMessage::PeerJoined {
-                                                                        peer_id: peer_id.to_string(),
-                                                                    })
-                                                                    .await;
-                                                            }
-                                                            Ok(
-                                                                iroh_gossip::api::Event::NeighborDown(
-                                                                    peer_id,
-                                                                ),
-                                                            ) => {
-
-                                                                let _ = tx_clone
-                                                                    .send(ServerMessage::PeerLeft {
-                                                                        peer_id: peer_id.to_string(),
-                                                                    })
-                                                                    .await;
-                                                            }
-                                                            _ => {}
-                                                        }
-                                                    }
-                                                });
-                                                current_receiver_task = Some(handle);
                                             }
                                             Err(e) => {
                                                 error!("[JOIN] Subscribe failed: {}", e);
@@ -352,6 +379,7 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                         warn!("[JOIN] Invalid ticket format: {}", ticket);
                                         let _ = to_client_tx
                                             .send(ServerMessage::InvalidTicket {
+                                                room_id,
                                                 ticket: ticket.clone(),
                                                 reason: "Invalid ticket format. Expected: topic_hex|node_id".to_string(),
                                             })
@@ -360,17 +388,14 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                 }
                             }
                             ClientMessage::JoinViaNodeMaster {
+                                room_id,
                                 ticket,
                                 nodemaster_url,
                             } => {
-                                // Connect to NodeMaster for peer discovery
-
-                                // Abort previous tasks
-                                if let Some(handle) = current_receiver_task.take() {
-                                    handle.abort();
-                                }
-                                if let Some(handle) = nodemaster_event_task.take() {
-                                    handle.abort();
+                                if let Some(topic_id) = find_topic_for_room(&rooms, &room_id) {
+                                    if let Some(handle) = rooms.remove(&topic_id) {
+                                        handle.abort();
+                                    }
                                 }
 
                                 // Connect to NodeMaster
@@ -378,14 +403,15 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                 match NodeMasterClient::connect(nm_url).await {
                                     Ok((client, mut event_rx)) => {
                                         // Register with ticket
-                                        client.register(ticket.clone(), my_node_id.clone());
-                                        _nodemaster_client = Some(client);
+                                        client.register(ticket.clone());
 
                                         // Wait for initial peer list
                                         let initial_peers =
                                             if let Some(event) = event_rx.recv().await {
                                                 match event {
-                                                    NodeMasterEvent::PeerList(peers) => peers,
+                                                    NodeMasterEvent::PeerList { node_ids, .. } => {
+                                                        node_ids
+                                                    }
                                                     _ => vec![],
                                                 }
                                             } else {
@@ -398,121 +424,54 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                             .filter_map(|p| EndpointId::from_str(p).ok())
                                             .collect();
 
-                                        // Create topic from ticket hash
-                                        let topic_bytes: [u8; 32] = match hex::decode(&ticket) {
-                                            Ok(bytes) if bytes.len() == 32 => {
-                                                bytes.try_into().unwrap()
-                                            }
-                                            _ => {
-                                                // Hash the ticket if it's not already a valid hex
-                                                use std::collections::hash_map::DefaultHasher;
-                                                use std::hash::{Hash, Hasher};
-                                                let mut hasher = DefaultHasher::new();
-                                                ticket.hash(&mut hasher);
-                                                let hash = hasher.finish();
-                                                // Generate 32 bytes from hash
-                                                let mut bytes = [0u8; 32];
-                                                bytes[..8].copy_from_slice(&hash.to_le_bytes());
-                                                bytes[8..16].copy_from_slice(&hash.to_be_bytes());
-                                                bytes[16..24].copy_from_slice(&hash.to_le_bytes());
-                                                bytes[24..32].copy_from_slice(&hash.to_be_bytes());
-                                                bytes
-                                            }
-                                        };
+                                        let topic_bytes = derive_topic_bytes(&ticket);
                                         let topic_id = iroh_gossip::TopicId::from(topic_bytes);
 
                                         // Subscribe to gossip with peers from NodeMaster
-                                        match gossip
-                                            .subscribe(topic_id, bootstrap_peers.clone())
-                                            .await
-                                        {
+                                        match gossip.subscribe(topic_id, bootstrap_peers).await {
                                             Ok(sub) => {
-                                                _current_topic = Some(topic_id);
-
-                                                let (sender, mut stream) = sub.split();
-                                                current_topic_sender = Some(sender.clone());
+                                                let (sender, stream) = sub.split();
+                                                let room_state = Arc::new(RoomState::default());
+                                                let handler: Arc<dyn GossipHandler> =
+                                                    Arc::new(SkinSyncHandler);
+                                                let ctx = HandlerCtx {
+                                                    room_id: room_id.clone(),
+                                                    my_node_id: my_node_id.clone(),
+                                                    secret_key: secret_key.clone(),
+                                                    sender: sender.clone(),
+                                                    to_client_tx: to_client_tx.clone(),
+                                                    state: room_state.clone(),
+                                                };
+                                                let receiver_task =
+                                                    spawn_gossip_receiver(handler, ctx, stream);
 
-                                                // Send confirmation
                                                 let _ = to_client_tx
                                                     .send(ServerMessage::JoinedRoom {
+                                                        room_id: room_id.clone(),
                                                         ticket: ticket.clone(),
                                                     })
                                                     .await;
 
-                                                // Task to handle gossip events
-                                                let tx_clone = to_client_tx.clone();
-                                                let my_node_id_clone = my_node_id.clone();
-                                                let sender_clone = sender.clone();
-                                                let gossip_handle = tokio::spawn(async move {
-                                                    while let Some(event_res) = stream.next().await
-                                                    {
-                                                        match event_res {
-                                                            Ok(iroh_gossip::api::Event::Received(msg)) => {
-                                                                if let Ok(gossip_msg) = serde_json::from_slice::<GossipMessage>(&msg.content) {
-                                                                    match gossip_msg {
-                                                                        GossipMessage::SkinUpdate { peer_id, skin_id, champion_id, skin_name, is_custom } => {
-
-                                                                            let _ = tx_clone.send(ServerMessage::RemoteSkinUpdate {
-                                                                                peer_id: peer_id.clone(),
-                                                                                skin_id, champion_id, skin_name, is_custom
-                                                                            }).await;
-                                                                            // Auto ACK
-                                                                            let ack = GossipMessage::SkinAck { target_peer_id: peer_id };
-                                                                            if let Ok(json) = serde_json::to_vec(&ack) {
-                                                                                sender_clone.broadcast(Bytes::from(json)).await.ok();
-                                                                            }
-                                                                        }
-                                                                        GossipMessage::SkinAck { target_peer_id } => {
-                                                                            if target_peer_id == my_node_id_clone {
-                                                                                let _ = tx_clone.send(ServerMessage::SyncConfirmed {
-                                                                                    peer_id: msg.delivered_from.to_string(),
-                                                                                }).await;
-                                                                            }
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                            Ok(iroh_gossip::api::Event::NeighborUp(peer_id)) => {
-
-                                                                let _ = tx_clone.send(ServerMessage::PeerJoined {
-                                                                    peer_id: peer_id.to_string(),
-                                                                }).await;
-                                                            }
-                                                            Ok(iroh_gossip::api::Event::NeighborDown(peer_id)) => {
-
-                                                                let _ = tx_clone.send(ServerMessage::PeerLeft {
-                                                                    peer_id: peer_id.to_string(),
-                                                                }).await;
-                                                            }
-                                                            _ => {}
-                                                        }
-                                                    }
-                                                });
-                                                current_receiver_task = Some(gossip_handle);
-
                                                 // Task to handle NodeMaster events (new peers joining later)
-                                                let _gossip_clone = gossip.clone();
-                                                let _tx_clone2 = to_client_tx.clone();
-                                                let _topic_id_clone = topic_id;
                                                 let tx_clone = to_client_tx.clone();
+                                                let room_id_clone = room_id.clone();
                                                 let nm_handle = tokio::spawn(async move {
                                                     while let Some(event) = event_rx.recv().await {
                                                         match event {
-                                                            NodeMasterEvent::PeerJoined(
-                                                                node_id,
-                                                            ) => {
-                                                                // Add new peer to gossip
-                                                                if let Ok(_endpoint_id) =
-                                                                    EndpointId::from_str(&node_id)
-                                                                {
-
-                                                                    // Re-subscribe with new peer to add them
-                                                                    // Note: iroh-gossip handles this via ALPN discovery
-                                                                }
+                                                            NodeMasterEvent::PeerJoined {
+                                                                ..
+                                                            } => {
+                                                                // iroh-gossip discovers the peer
+                                                                // itself once it's reachable;
+                                                                // nothing to do here.
                                                             }
-                                                            NodeMasterEvent::PeerLeft(node_id) => {
+                                                            NodeMasterEvent::PeerLeft {
+                                                                node_id,
+                                                                ..
+                                                            } => {
                                                                 let _ = tx_clone
                                                                     .send(ServerMessage::PeerLeft {
+                                                                        room_id: room_id_clone.clone(),
                                                                         peer_id: node_id,
                                                                     })
                                                                     .await;
@@ -527,7 +486,19 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                                         }
                                                     }
                                                 });
-                                                nodemaster_event_task = Some(nm_handle);
+
+                                                rooms.insert(
+                                                    topic_id,
+                                                    RoomHandle {
+                                                        room_id,
+                                                        ticket,
+                                                        sender,
+                                                        state: room_state,
+                                                        receiver_task,
+                                                        nodemaster_client: Some(client),
+                                                        nodemaster_event_task: Some(nm_handle),
+                                                    },
+                                                );
                                             }
                                             Err(e) => {
                                                 error!(
@@ -559,70 +530,73 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
                                 }
                             }
                             ClientMessage::UpdateSkin {
+                                room_id,
                                 skin_id,
                                 champion_id,
                                 skin_name,
                                 is_custom,
                             } => {
-                                if let Some(sender) = &current_topic_sender {
+                                if let Some(handle) =
+                                    rooms.values().find(|h| h.room_id == room_id)
+                                {
+                                    handle.state.skins.lock().await.insert(
+                                        my_node_id.clone(),
+                                        SkinEntry {
+                                            peer_id: my_node_id.clone(),
+                                            skin_id,
+                                            champion_id,
+                                            skin_name: skin_name.clone(),
+                                            is_custom,
+                                        },
+                                    );
+                                    let update_id = format!("{:x}", rand::random::<u128>());
                                     let payload = GossipMessage::SkinUpdate {
                                         peer_id: my_node_id.clone(),
                                         skin_id,
                                         champion_id,
                                         skin_name,
                                         is_custom,
+                                        update_id: update_id.clone(),
                                     };
-                                    if let Ok(json) = serde_json::to_vec(&payload) {
-                                        match sender.broadcast(Bytes::from(json)).await {
-                                            Ok(_) => {}
-                                            Err(e) => {
-                                                error!("[TX] Broadcast failed: {}", e);
-                                                let _ = to_client_tx
-                                                    .send(ServerMessage::Log {
-                                                        level: "ERROR".to_string(),
-                                                        message: format!("Broadcast failed: {}", e),
-                                                    })
-                                                    .await;
-                                            }
-                                        }
-                                    }
+                                    gossip_handler::broadcast_reliable(
+                                        secret_key.clone(),
+                                        handle.sender.clone(),
+                                        handle.state.clone(),
+                                        update_id,
+                                        payload,
+                                    )
+                                    .await;
                                 } else {
-                                    error!("[TX] No topic sender! Not in any room.");
+                                    error!("[TX] No topic sender for room {}", room_id);
                                     let _ = to_client_tx
                                         .send(ServerMessage::Error {
-                                            message: "Not in any room. Call JoinTicket first."
-                                                .to_string(),
+                                            message: format!(
+                                                "Not in room {}. Call JoinTicket first.",
+                                                room_id
+                                            ),
                                         })
                                         .await;
                                 }
                             }
-                            ClientMessage::ReportPeerLeft { node_id } => {
-                                if let Some(ref client) = _nodemaster_client {
-                                    client.report_peer_left(node_id);
+                            ClientMessage::ReportPeerLeft { room_id, node_id } => {
+                                if let Some(handle) =
+                                    rooms.values().find(|h| h.room_id == room_id)
+                                {
+                                    if let Some(ref client) = handle.nodemaster_client {
+                                        client.report_peer_left(node_id);
+                                    }
                                 }
                             }
-                            ClientMessage::LeaveRoom => {
-                                // 1. Notify NodeMaster (if connected)
-                                if let Some(ref client) = _nodemaster_client {
-                                    client.leave();
-                                }
-                                _nodemaster_client = None;
-
-                                // 2. Abort gossip receiver task
-                                if let Some(handle) = current_receiver_task.take() {
-                                    handle.abort();
-                                }
-
-                                // 3. Abort NodeMaster event task
-                                if let Some(handle) = nodemaster_event_task.take() {
-                                    handle.abort();
+                            ClientMessage::LeaveRoom { room_id } => {
+                                if let Some(topic_id) = find_topic_for_room(&rooms, &room_id) {
+                                    if let Some(handle) = rooms.remove(&topic_id) {
+                                        handle.abort();
+                                    }
                                 }
 
-                                // 4. Drop gossip sender (triggers NeighborDown for peers)
-                                current_topic_sender = None;
-                                _current_topic = None;
-
-                                let _ = to_client_tx.send(ServerMessage::LeftRoom).await;
+                                let _ = to_client_tx
+                                    .send(ServerMessage::LeftRoom { room_id })
+                                    .await;
                             }
                         }
                     }
@@ -632,8 +606,8 @@ pub async fn handle_connection(stream: TcpStream, gossip: Gossip, my_node_id: St
         }
     }
 
-    // Cleanup on disconnect
-    if let Some(handle) = current_receiver_task {
+    // Cleanup on disconnect: tear down every room this connection was subscribed to
+    for (_, handle) in rooms.drain() {
         handle.abort();
     }
 }
@@ -0,0 +1,138 @@
+//! Optional UPnP/IGD port mapping for the gossip endpoint.
+//!
+//! Maps the endpoint's bound local UDP port to an equal external port on the
+//! gateway, so peers behind the same NAT type can hole-punch a direct connection
+//! instead of always falling back to the relay network. Best-effort: any failure
+//! here just means we stay relay-only, which is already the non-UPnP behavior.
+
+use std::net::{IpAddr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use igd_next::aio::tokio::search_gateway;
+use igd_next::{PortMappingProtocol, SearchOptions};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// How long each port mapping lease lasts before it must be renewed
+const LEASE_SECS: u32 = 600;
+/// Renew comfortably before the lease expires
+const RENEW_INTERVAL: Duration = Duration::from_secs(480);
+
+/// A live UPnP mapping and the background task that keeps renewing its lease
+pub struct UpnpMapping {
+    pub external_addr: SocketAddr,
+    gateway: igd_next::aio::tokio::Gateway,
+    external_port: u16,
+    renew_task: JoinHandle<()>,
+}
+
+impl UpnpMapping {
+    /// Tear down the mapping and stop renewing it
+    pub async fn unmap(self) {
+        self.renew_task.abort();
+        if let Err(e) = self
+            .gateway
+            .remove_port(PortMappingProtocol::UDP, self.external_port)
+            .await
+        {
+            warn!("Failed to remove UPnP port mapping: {}", e);
+        } else {
+            info!("UPnP port mapping removed");
+        }
+    }
+}
+
+/// Search for an internet gateway and map `local_port` (UDP) to the same external
+/// port, renewing the lease on a timer. Returns `None` (after logging) on any failure
+/// rather than propagating an error, since UPnP is always an optional enhancement.
+pub async fn map_port(local_port: u16) -> Option<UpnpMapping> {
+    let gateway = match search_gateway(SearchOptions::default()).await {
+        Ok(gw) => gw,
+        Err(e) => {
+            warn!("UPnP: no internet gateway found: {}", e);
+            return None;
+        }
+    };
+
+    let local_ip = match gateway.get_external_ip().await {
+        // get_external_ip is also a reachability probe; the LAN address used for the
+        // mapping is the one the OS routes outbound traffic from, found by `local_ipv4`
+        Ok(_) => match local_ipv4() {
+            Some(ip) => ip,
+            None => {
+                warn!("UPnP: could not determine local IPv4 address");
+                return None;
+            }
+        },
+        Err(e) => {
+            warn!("UPnP: gateway unreachable: {}", e);
+            return None;
+        }
+    };
+
+    let local_addr = SocketAddrV4::new(local_ip, local_port);
+    let external_ip = match gateway
+        .add_port(
+            PortMappingProtocol::UDP,
+            local_port,
+            local_addr,
+            LEASE_SECS,
+            "rose-sidecar",
+        )
+        .await
+    {
+        Ok(()) => match gateway.get_external_ip().await {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("UPnP: mapped port but failed to read external IP: {}", e);
+                return None;
+            }
+        },
+        Err(e) => {
+            warn!("UPnP: port mapping request failed: {}", e);
+            return None;
+        }
+    };
+
+    let external_addr = SocketAddr::new(IpAddr::V4(external_ip), local_port);
+    info!("UPnP: mapped external address {}", external_addr);
+
+    let renew_gateway = gateway.clone();
+    let renew_task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RENEW_INTERVAL);
+        interval.tick().await; // first tick fires immediately
+        loop {
+            interval.tick().await;
+            if let Err(e) = renew_gateway
+                .add_port(
+                    PortMappingProtocol::UDP,
+                    local_port,
+                    local_addr,
+                    LEASE_SECS,
+                    "rose-sidecar",
+                )
+                .await
+            {
+                warn!("UPnP: failed to renew port mapping lease: {}", e);
+            }
+        }
+    });
+
+    Some(UpnpMapping {
+        external_addr,
+        gateway,
+        external_port: local_port,
+        renew_task,
+    })
+}
+
+/// Best-effort local IPv4 address discovery by opening a UDP socket toward a
+/// routable address without sending anything
+fn local_ipv4() -> Option<std::net::Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("1.1.1.1:80").ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    }
+}